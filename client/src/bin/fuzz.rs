@@ -0,0 +1,133 @@
+use clap::Parser;
+use client::config::ConnectArgs;
+use client::*;
+use protocol::mutate::mutate;
+use protocol::*;
+use session::{Error, Session};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Outcome {
+    Disconnected,
+    NoResponse,
+    Garbled,
+    Code(u32),
+    NoCode,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Disconnected => f.write_str("disconnected"),
+            Self::NoResponse => f.write_str("no response"),
+            Self::Garbled => f.write_str("garbled response"),
+            Self::Code(code) => write!(f, "code {:#x}", code),
+            Self::NoCode => f.write_str("response without code"),
+        }
+    }
+}
+
+fn exchange(session: &mut Session<TcpStream>, plaintext: &[u8]) -> Outcome {
+    match session.request(plaintext) {
+        Ok(message) => message.code().map(Outcome::Code).unwrap_or(Outcome::NoCode),
+        Err(Error::Io(e)) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => Outcome::NoResponse,
+        Err(Error::Io(_)) => Outcome::Disconnected,
+        Err(_) => Outcome::Garbled,
+    }
+}
+
+/// sends mutated messages to a server and records the ones it handles differently from the original
+#[derive(Debug, Parser)]
+struct Args {
+    #[command(flatten)]
+    connect: ConnectArgs,
+    /// every finding gets appended here
+    #[arg(long, default_value = "findings.txt")]
+    findings: PathBuf,
+}
+
+// every run gets a fresh connection so one bad input can't poison the next
+fn run(config: &Config, init: bool, plaintext: &[u8]) -> std::io::Result<Outcome> {
+    let stream = TcpStream::connect(&config.server)?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let key = config.session_key().unwrap_or_else(|e| panic!("{}", e));
+    let mut session = match Session::handshake(stream, &config.fingerprint, &config.server_key, key) {
+        Ok(session) => session,
+        Err(Error::Io(e)) => return Err(e),
+        Err(e) => panic!("{}", e),
+    };
+    if init {
+        match exchange(&mut session, Message::make_init(config.uuid).as_bytes()) {
+            Outcome::Code(_) | Outcome::NoCode => {}
+            outcome => return Ok(outcome),
+        }
+    }
+    Ok(exchange(&mut session, plaintext))
+}
+
+fn main() {
+    let args = Args::parse();
+    let config = match args.connect.into_profile() {
+        Ok(profile) => profile.into_config(),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = config.session_key() {
+        eprintln!("can't derive the session key: {}", e);
+        std::process::exit(1);
+    }
+    let mut findings_file = match File::options().create(true).append(true).open(&args.findings) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("can't open {}: {}", args.findings.display(), e);
+            std::process::exit(1);
+        }
+    };
+
+    let uuid = config.uuid;
+    let seeds = [
+        ("init", false, message! { cmd: Init, uuid: uuid }),
+        ("session", true, message! { cmd: GetSessionFolder, uuid: uuid }),
+        ("ls", true, message! { cmd: ListDir, uuid: uuid, dir: "/" }),
+        ("get", true, message! { cmd: ReadFile, uuid: uuid, dir: "/", file: "a.txt" }),
+        ("upload", true, message! { cmd: Upload, uuid: uuid, dir: "/tmp", file: "a.txt", contents: b"AAAA" }),
+    ];
+
+    let mut findings = 0;
+    for (name, init, seed) in seeds.iter() {
+        let baseline = match run(&config, *init, seed.as_bytes()) {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                eprintln!("{}: couldn't talk to {}: {}", name, config.server, e);
+                std::process::exit(1);
+            }
+        };
+        println!("{}: baseline {}", name, baseline);
+
+        for mutant in mutate(seed) {
+            let outcome = match run(&config, *init, &mutant.bytes) {
+                Ok(outcome) => outcome,
+                Err(_) => Outcome::Disconnected,
+            };
+            println!("{}: {} => {}", name, mutant.mutation, outcome);
+            if outcome != baseline {
+                findings += 1;
+                let line = format!("{}\t{}\t{} (baseline {})\t{}\n", name, mutant.mutation, outcome, baseline, hex::encode(&mutant.bytes));
+                if let Err(e) = findings_file.write_all(line.as_bytes()) {
+                    eprintln!("can't write to {}: {}", args.findings.display(), e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+    println!("{} findings, written to {}", findings, args.findings.display());
+}
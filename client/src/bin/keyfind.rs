@@ -0,0 +1,131 @@
+use clap::Parser;
+use session::fingerprint::parse_timestamp;
+use session::{decrypt, Fingerprint, Key, KeyFormat};
+use rayon::prelude::*;
+
+// length header, nonce and the mac; anything shorter can't be a frame
+const MIN_FRAME_LEN: usize = 4 + 24 + 16;
+
+/// finds the fingerprint whose session key opens a captured frame
+#[derive(Debug, Parser)]
+struct Args {
+    /// hex of an encrypted frame (length header, nonce, ciphertext)
+    frame: String,
+    /// candidate username, repeatable
+    #[arg(long = "username", required = true)]
+    usernames: Vec<String>,
+    /// candidate version, repeatable
+    #[arg(long = "implant-version", required = true)]
+    versions: Vec<String>,
+    /// os to put in the candidate fingerprints, only matters if the key format uses it
+    #[arg(long, default_value = "Linux")]
+    os: String,
+    /// first timestamp to try, unix or `now`
+    #[arg(long, value_parser = parse_timestamp)]
+    from: u64,
+    /// last timestamp to try, inclusive
+    #[arg(long, value_parser = parse_timestamp)]
+    to: u64,
+    #[arg(long, default_value_t = KeyFormat::default())]
+    key_format: KeyFormat,
+    /// defaults to one per core
+    #[arg(long)]
+    threads: Option<usize>,
+}
+
+/// every username and version at every timestamp from `--from` to `--to`, until one of them opens
+/// `frame`
+fn search(args: &Args, frame: &[u8]) -> Result<Option<(Fingerprint, Key, Vec<u8>)>, String> {
+    if args.from > args.to {
+        return Err(format!("--from {} is after --to {}", args.from, args.to));
+    }
+    let sample = Fingerprint { os: args.os.clone(), ..Fingerprint::default() };
+    args.key_format.render(&sample).map_err(|e| format!("bad key format: {}", e))?;
+
+    let candidates = args
+        .usernames
+        .iter()
+        .flat_map(|username| args.versions.iter().map(move |version| (username, version)))
+        .collect::<Vec<_>>();
+    eprintln!("trying {} candidates", candidates.len() as u64 * (args.to - args.from + 1));
+
+    Ok((args.from..=args.to).into_par_iter().find_map_any(|timestamp| {
+        candidates.iter().find_map(|(username, version)| {
+            let fingerprint = Fingerprint {
+                username: username.to_string(),
+                version: version.to_string(),
+                os: args.os.clone(),
+                timestamp,
+                extra: Vec::new(),
+            };
+            let key = fingerprint.session_key(&args.key_format).ok()?;
+            decrypt(&key, frame).ok().map(|plaintext| (fingerprint, key, plaintext))
+        })
+    }))
+}
+
+fn main() {
+    let args = Args::parse();
+    let frame = match hex::decode(args.frame.trim()) {
+        Ok(frame) if frame.len() >= MIN_FRAME_LEN => frame,
+        Ok(frame) => {
+            eprintln!("frame is {} bytes, it needs at least {}", frame.len(), MIN_FRAME_LEN);
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("frame isn't hex: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new().num_threads(threads).build_global().unwrap();
+    }
+
+    match search(&args, &frame) {
+        Ok(Some((fingerprint, key, plaintext))) => {
+            println!("username:  {}", fingerprint.username);
+            println!("version:   {}", fingerprint.version);
+            println!("timestamp: {}", fingerprint.timestamp);
+            println!("key input: {}", args.key_format.render(&fingerprint).unwrap());
+            println!("key:       {}", hex::encode(key));
+            println!("plaintext: {}", hex::encode(plaintext));
+        }
+        Ok(None) => {
+            eprintln!("no candidate opens the frame");
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::message;
+    use session::encrypt;
+
+    fn args(frame: &[u8], from: &str, to: &str) -> Args {
+        let frame = hex::encode(frame);
+        Args::parse_from(["keyfind", &frame, "--username", "alice", "--username", "bob", "--implant-version", "2.1.3.0-PQF", "--from", from, "--to", to])
+    }
+
+    #[test]
+    fn test_search() {
+        let fingerprint = Fingerprint { username: "bob".to_string(), timestamp: 1634050100, ..Fingerprint::default() };
+        let key = fingerprint.session_key(&KeyFormat::default()).unwrap();
+        let request = message! { cmd: ListDir, uuid: "000102030405060708090a0b0c0d0f10", dir: "/" };
+        let frame = encrypt(&key, request.as_bytes().to_vec()).unwrap();
+
+        let (found, found_key, plaintext) = search(&args(&frame, "1634050000", "1634050200"), &frame).unwrap().unwrap();
+        assert_eq!((found.username.as_str(), found.timestamp), ("bob", 1634050100));
+        assert_eq!(found_key, key);
+        assert_eq!(plaintext, request.as_bytes());
+
+        assert_eq!(search(&args(&frame, "1634050000", "1634050099"), &frame), Ok(None));
+        // used to say it was trying a pile of candidates and then try none
+        assert!(search(&args(&frame, "1634050200", "1634050000"), &frame).is_err());
+    }
+}
@@ -0,0 +1,69 @@
+use clap::Parser;
+use client::config::ConnectArgs;
+use client::replay::{diff, read_recording};
+use client::Transport;
+use protocol::parse;
+use std::path::PathBuf;
+use tokio::time::timeout;
+
+/// sends the requests in a recording to a server again and checks the responses against it
+#[derive(Debug, Parser)]
+struct Args {
+    /// a session log, or `>` and `<` lines of plaintext hex
+    recording: PathBuf,
+    #[command(flatten)]
+    connect: ConnectArgs,
+}
+
+fn fail(message: impl std::fmt::Display) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(2);
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    let config = args.connect.into_profile().unwrap_or_else(|e| fail(e)).into_config();
+    let text = std::fs::read_to_string(&args.recording).unwrap_or_else(|e| fail(format!("can't read {}: {}", args.recording.display(), e)));
+    let exchanges = read_recording(&text).unwrap_or_else(|e| fail(e));
+
+    // the recording has its own Init, so nothing is sent past the handshake
+    let mut transport = Transport::connect(&config).await.unwrap_or_else(|e| fail(format!("couldn't connect: {}", e)));
+    let mut diverged = 0;
+    for (i, exchange) in exchanges.iter().enumerate() {
+        let command = match parse(&exchange.request) {
+            Ok((_, message)) => message.command().map(|x| format!("{:?}", x)).unwrap_or_else(|| "no command".to_string()),
+            Err(_) => "unparseable".to_string(),
+        };
+        let request = transport.request_raw(&exchange.request);
+        let response = match config.request_timeout {
+            Some(limit) => timeout(limit, request).await.unwrap_or(Err(session::Error::Timeout)),
+            None => request.await,
+        };
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                println!("#{} {}: {}, stopping", i + 1, command, e);
+                diverged += exchanges.len() - i;
+                break;
+            }
+        };
+        match &exchange.response {
+            Some(expected) => {
+                let lines = diff(expected, &response);
+                if lines.is_empty() {
+                    println!("#{} {}: same", i + 1, command);
+                } else {
+                    diverged += 1;
+                    println!("#{} {}: diverged", i + 1, command);
+                    lines.iter().for_each(|x| println!("    {}", x));
+                }
+            }
+            None => println!("#{} {}: nothing recorded, got {}", i + 1, command, hex::encode(&response)),
+        }
+    }
+    println!("{} of {} diverged", diverged, exchanges.len());
+    if diverged > 0 {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,197 @@
+use std::time::Duration;
+
+use protocol::*;
+use session::frame::MAX_PLAINTEXT_LEN;
+use session::{Error, Result};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+use crate::log::{Direction, SessionLog};
+use crate::{Config, Transport};
+
+/// what the repl does, minus the repl: a session with our uuid and a working directory on the target
+///
+/// every request is cancel safe, see [`Transport::request`]. if the connection drops, the client
+/// reconnects as the same uuid following [`Config::retry`], and `ls`/`get` are retried. a garbled
+/// stream, a timed out `ls`/`get`, or two timeouts in a row count as a drop too
+pub struct Client {
+    transport: Transport<TcpStream>,
+    config: Config,
+    cwd: RemotePath,
+    log: Option<SessionLog>,
+    // requests in a row that got no response in time
+    timeouts: u32,
+}
+
+impl Client {
+    /// connects, does the handshake and sends Init
+    pub async fn connect(config: &Config) -> Result<Self> {
+        let transport = Transport::connect(config).await?;
+        let mut client = Client { transport, config: config.clone(), cwd: RemotePath::root(config.path_style), log: None, timeouts: 0 };
+        if let Some(path) = &config.log {
+            client.set_log(SessionLog::open(path, config.log_rotation)?);
+        }
+        client.init().await?;
+        Ok(client)
+    }
+
+    /// every request and response gets written here
+    pub fn set_log(&mut self, log: SessionLog) {
+        self.log = Some(log);
+    }
+
+    /// how long to wait for each response; `None` waits forever
+    pub fn set_request_timeout(&mut self, limit: Option<Duration>) {
+        self.config.request_timeout = limit;
+    }
+
+    pub fn transport(&mut self) -> &mut Transport<TcpStream> {
+        &mut self.transport
+    }
+
+    /// a new connection, handshake and Init as the same uuid, trying as often as the retry policy
+    /// says; the working directory is only ours, so it carries over
+    pub async fn reconnect(&mut self) -> Result<()> {
+        let mut delay = self.config.retry.delay;
+        let mut attempts = self.config.retry.attempts.max(1);
+        loop {
+            let result = match Transport::connect(&self.config).await {
+                Ok(transport) => {
+                    self.transport = transport;
+                    self.timeouts = 0;
+                    self.init().await
+                }
+                Err(e) => Err(e),
+            };
+            attempts -= 1;
+            match result {
+                Ok(_) => return Ok(()),
+                Err(e) if attempts == 0 => return Err(e),
+                Err(_) => {
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+            }
+        }
+    }
+
+    // one go at a request, no reconnecting
+    async fn exchange(&mut self, message: &Message) -> Result<ParsedMessage> {
+        if let Some(log) = &mut self.log {
+            log.record(Direction::Request, message.as_bytes())?;
+        }
+        let response = match self.config.request_timeout {
+            Some(limit) => timeout(limit, self.transport.request_raw(message.as_bytes())).await.map_err(|_| Error::Timeout)??,
+            None => self.transport.request_raw(message.as_bytes()).await?,
+        };
+        if let Some(log) = &mut self.log {
+            log.record(Direction::Response, &response)?;
+        }
+        let response = match parse(&response) {
+            Ok((_, response)) => response,
+            Err(_) => return Err(Error::Parse),
+        };
+        match response.code() {
+            Some(0) | None => Ok(response),
+            Some(code) => Err(Error::Code(code)),
+        }
+    }
+
+    // whether `error` means the connection's no good any more
+    fn is_dead(&self, error: &Error, idempotent: bool) -> bool {
+        match error {
+            Error::Io(_) => true,
+            // the late response is still owed, and everything after it queues up behind it; a
+            // second timeout means it isn't coming
+            Error::Timeout => idempotent || self.timeouts > 1,
+            // a garbled stream won't come right again either
+            error => error.is_desync(),
+        }
+    }
+
+    // reconnects when the connection's gone; only sends `message` again if it's safe to
+    async fn send(&mut self, message: &Message, idempotent: bool) -> Result<ParsedMessage> {
+        let mut retries = self.config.retry.attempts;
+        loop {
+            let result = self.exchange(message).await;
+            // anything but a timeout means the late response turned up, or the connection's going
+            self.timeouts = match &result {
+                Err(Error::Timeout) => self.timeouts + 1,
+                _ => 0,
+            };
+            match result {
+                Err(e) if self.is_dead(&e, idempotent) && retries > 0 => {
+                    retries -= 1;
+                    self.reconnect().await?;
+                    if !idempotent {
+                        return Err(e);
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// sends `message` and waits for the response; a nonzero code is an error
+    ///
+    /// a dropped connection gets reestablished, but `message` isn't sent again since we can't
+    /// know if the server saw it. one timeout leaves the connection be, a second in a row drops it
+    pub async fn request(&mut self, message: &Message) -> Result<ParsedMessage> {
+        self.send(message, false).await
+    }
+
+    pub async fn init(&mut self) -> Result<ParsedMessage> {
+        self.exchange(&Message::make_init(self.config.uuid)).await
+    }
+
+    pub fn cwd(&self) -> &RemotePath {
+        &self.cwd
+    }
+
+    /// only moves our idea of the working directory; the server isn't asked
+    pub fn cd(&mut self, path: &str) {
+        self.cwd.push(path);
+    }
+
+    /// the folder the server keeps for our uuid
+    pub async fn session_folder(&mut self) -> Result<RemotePath> {
+        let response = self.request(&message! { cmd: GetSessionFolder, uuid: self.config.uuid }).await?;
+        match response.get(ParamKind::DirName).and_then(Param::as_str) {
+            Some(dir) => Ok(RemotePath::new(dir, self.cwd.style())),
+            None => Err(Error::Parse),
+        }
+    }
+
+    pub async fn ls(&mut self) -> Result<Vec<String>> {
+        let response = self.send(&Message::make_list_dir(self.config.uuid, &self.cwd), true).await?;
+        Ok(response.get_all(ParamKind::FolderContents).filter_map(Param::as_str).map(str::to_string).collect())
+    }
+
+    /// the contents of `file` in the working directory
+    pub async fn get(&mut self, file: &str) -> Result<Vec<u8>> {
+        let response = self.send(&Message::make_read_file(self.config.uuid, &self.cwd, file), true).await?;
+        Ok(response.get_all(ParamKind::Contents).filter_map(Param::as_bytes).flatten().copied().collect())
+    }
+
+    /// writes `file` in the working directory; it all has to fit in one frame, there's no
+    /// appending to a file
+    pub async fn upload(&mut self, file: &str, contents: &[u8]) -> Result<()> {
+        // too long for the narrow size in front of the contents, let alone a frame
+        if contents.len() > MAX_PLAINTEXT_LEN {
+            return Err(Error::TooLong(contents.len()));
+        }
+        let cwd = self.cwd.to_string();
+        let message = message! { cmd: Upload, uuid: self.config.uuid, dir: cwd, file: file, contents: contents.to_vec() };
+        if message.as_bytes().len() > MAX_PLAINTEXT_LEN {
+            return Err(Error::TooLong(message.as_bytes().len()));
+        }
+        self.request(&message).await?;
+        Ok(())
+    }
+
+    /// tells the server we're done; no point reconnecting for it
+    pub async fn fin(mut self) -> Result<()> {
+        self.exchange(&message! { cmd: Fin, uuid: self.config.uuid }).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,450 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use protocol::PathStyle;
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+use session::fingerprint::{parse_field, parse_timestamp};
+use session::{parse_key, parse_public_key, Fingerprint, FingerprintError, Key, KeyFormat, PublicKey};
+use thiserror::Error;
+
+use crate::log::Rotation;
+use crate::{SERVER_KEY, UUID};
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("can't read {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("bad profile {0}: {1}")]
+    Toml(PathBuf, toml::de::Error),
+}
+
+/// what to do when the connection drops
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct RetryPolicy {
+    /// connection attempts per drop, and how many times a request gets retried; 0 never reconnects
+    pub attempts: u32,
+    /// wait after the first failed attempt, doubled after each one
+    pub delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy { attempts: 3, delay: Duration::from_secs(1) }
+    }
+}
+
+/// everything needed to reach a target
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// `host:port` of the server
+    pub server: String,
+    pub uuid: [u8; 16],
+    /// the handshake is sealed to this
+    pub server_key: PublicKey,
+    pub fingerprint: Fingerprint,
+    pub key_format: KeyFormat,
+    /// used as is instead of deriving one from the fingerprint
+    pub key: Option<Key>,
+    /// every request and response gets appended here, a line of json each
+    pub log: Option<PathBuf>,
+    pub log_rotation: Rotation,
+    /// where `get` puts files
+    pub download_dir: PathBuf,
+    /// how paths look on the target; the working directory starts at its root
+    pub path_style: PathStyle,
+    /// for the tcp connection and the handshake
+    pub connect_timeout: Duration,
+    /// how long to wait for each response; `None` waits forever
+    pub request_timeout: Option<Duration>,
+    pub retry: RetryPolicy,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            server: "127.0.0.1:6666".to_string(),
+            uuid: UUID,
+            server_key: SERVER_KEY,
+            fingerprint: Fingerprint::default(),
+            key_format: KeyFormat::default(),
+            key: None,
+            log: None,
+            log_rotation: Rotation::default(),
+            download_dir: PathBuf::from("received"),
+            path_style: PathStyle::default(),
+            connect_timeout: Duration::from_secs(10),
+            request_timeout: Some(Duration::from_secs(30)),
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn session_key(&self) -> Result<Key, FingerprintError> {
+        match &self.key {
+            Some(key) => Ok(key.clone()),
+            None => self.fingerprint.session_key(&self.key_format),
+        }
+    }
+}
+
+/// a uuid as 32 hex digits, dashes allowed
+pub fn parse_uuid(uuid: &str) -> Result<[u8; 16], String> {
+    let uuid = hex::decode(uuid.trim().replace('-', "")).map_err(|e| e.to_string())?;
+    uuid.as_slice().try_into().map_err(|_| format!("uuid is {} bytes, it needs 16", uuid.len()))
+}
+
+/// seconds, fractions allowed
+pub fn parse_seconds(seconds: &str) -> Result<Duration, String> {
+    seconds.trim().parse().ok().and_then(|x| Duration::try_from_secs_f64(x).ok()).ok_or_else(|| format!("{:?} isn't a number of seconds", seconds))
+}
+
+// an optional string value run through one of the command line parsers
+fn parsed<'de, D, T, E>(deserializer: D, parse: impl Fn(&str) -> Result<T, E>) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    E: fmt::Display,
+{
+    Option::<String>::deserialize(deserializer)?.map(|x| parse(&x).map_err(de::Error::custom)).transpose()
+}
+
+fn uuid<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<[u8; 16]>, D::Error> {
+    parsed(deserializer, parse_uuid)
+}
+
+fn public_key<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<PublicKey>, D::Error> {
+    parsed(deserializer, parse_public_key)
+}
+
+fn key<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Key>, D::Error> {
+    parsed(deserializer, parse_key)
+}
+
+fn key_format<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<KeyFormat>, D::Error> {
+    parsed(deserializer, str::parse::<KeyFormat>)
+}
+
+fn path_style<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<PathStyle>, D::Error> {
+    parsed(deserializer, str::parse::<PathStyle>)
+}
+
+fn timestamp<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Timestamp {
+        Unix(u64),
+        Text(String),
+    }
+    match Option::<Timestamp>::deserialize(deserializer)? {
+        Some(Timestamp::Unix(timestamp)) => Ok(Some(timestamp)),
+        Some(Timestamp::Text(timestamp)) => parse_timestamp(&timestamp).map(Some).map_err(de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+fn seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    match Option::<f64>::deserialize(deserializer)? {
+        Some(seconds) => Duration::try_from_secs_f64(seconds).map(Some).map_err(de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+fn fields<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<(String, String)>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?.iter().map(|x| parse_field(x).map_err(de::Error::custom)).collect()
+}
+
+/// fingerprint fields to claim; anything left out comes from the captured install
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FingerprintProfile {
+    pub username: Option<String>,
+    pub version: Option<String>,
+    pub os: Option<String>,
+    /// unix timestamp or `now`
+    #[serde(default, deserialize_with = "timestamp")]
+    pub timestamp: Option<u64>,
+    /// `key=value` strings
+    #[serde(default, deserialize_with = "fields")]
+    pub extra: Vec<(String, String)>,
+}
+
+/// a partial [`Config`], from a toml file or the command line
+///
+/// ```toml
+/// server = "10.0.0.2:6666"
+/// uuid = "c2cd31ed-2713-4010-a0de-dfc817a341b7"
+/// download_dir = "loot"
+///
+/// [fingerprint]
+/// username = "bob"
+/// timestamp = "now"
+/// extra = ["host=box"]
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub server: Option<String>,
+    #[serde(default, deserialize_with = "uuid")]
+    pub uuid: Option<[u8; 16]>,
+    /// hex
+    #[serde(default, deserialize_with = "public_key")]
+    pub server_key: Option<PublicKey>,
+    /// hex
+    #[serde(default, deserialize_with = "key")]
+    pub key: Option<Key>,
+    #[serde(default, deserialize_with = "key_format")]
+    pub key_format: Option<KeyFormat>,
+    pub log: Option<PathBuf>,
+    /// start a new log past this size
+    pub log_max_bytes: Option<u64>,
+    /// old logs to keep around
+    pub log_keep: Option<usize>,
+    pub download_dir: Option<PathBuf>,
+    /// `posix` or `windows`
+    #[serde(default, deserialize_with = "path_style")]
+    pub path_style: Option<PathStyle>,
+    /// seconds
+    #[serde(default, deserialize_with = "seconds")]
+    pub connect_timeout: Option<Duration>,
+    /// seconds, 0 waits forever
+    #[serde(default, deserialize_with = "seconds")]
+    pub request_timeout: Option<Duration>,
+    /// reconnect attempts, 0 doesn't reconnect
+    pub retries: Option<u32>,
+    /// seconds, doubled after each failed attempt
+    #[serde(default, deserialize_with = "seconds")]
+    pub retry_delay: Option<Duration>,
+    #[serde(default)]
+    pub fingerprint: FingerprintProfile,
+}
+
+impl Profile {
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        toml::from_str(&text).map_err(|e| ConfigError::Toml(path.to_path_buf(), e))
+    }
+
+    /// this profile, with anything it leaves out taken from `other`
+    pub fn or(self, other: Profile) -> Profile {
+        let (fingerprint, fallback) = (self.fingerprint, other.fingerprint);
+        Profile {
+            server: self.server.or(other.server),
+            uuid: self.uuid.or(other.uuid),
+            server_key: self.server_key.or(other.server_key),
+            key: self.key.or(other.key),
+            key_format: self.key_format.or(other.key_format),
+            log: self.log.or(other.log),
+            log_max_bytes: self.log_max_bytes.or(other.log_max_bytes),
+            log_keep: self.log_keep.or(other.log_keep),
+            download_dir: self.download_dir.or(other.download_dir),
+            path_style: self.path_style.or(other.path_style),
+            connect_timeout: self.connect_timeout.or(other.connect_timeout),
+            request_timeout: self.request_timeout.or(other.request_timeout),
+            retries: self.retries.or(other.retries),
+            retry_delay: self.retry_delay.or(other.retry_delay),
+            fingerprint: FingerprintProfile {
+                username: fingerprint.username.or(fallback.username),
+                version: fingerprint.version.or(fallback.version),
+                os: fingerprint.os.or(fallback.os),
+                timestamp: fingerprint.timestamp.or(fallback.timestamp),
+                extra: if fingerprint.extra.is_empty() { fallback.extra } else { fingerprint.extra },
+            },
+        }
+    }
+
+    /// fills in the defaults
+    pub fn into_config(self) -> Config {
+        let default = Config::default();
+        let fingerprint = self.fingerprint;
+        Config {
+            server: self.server.unwrap_or(default.server),
+            uuid: self.uuid.unwrap_or(default.uuid),
+            server_key: self.server_key.unwrap_or(default.server_key),
+            fingerprint: Fingerprint {
+                username: fingerprint.username.unwrap_or(default.fingerprint.username),
+                version: fingerprint.version.unwrap_or(default.fingerprint.version),
+                os: fingerprint.os.unwrap_or(default.fingerprint.os),
+                timestamp: fingerprint.timestamp.unwrap_or(default.fingerprint.timestamp),
+                extra: fingerprint.extra,
+            },
+            key_format: self.key_format.unwrap_or(default.key_format),
+            key: self.key,
+            log: self.log,
+            log_rotation: Rotation {
+                max_bytes: self.log_max_bytes.or(default.log_rotation.max_bytes),
+                keep: self.log_keep.unwrap_or(default.log_rotation.keep),
+            },
+            download_dir: self.download_dir.unwrap_or(default.download_dir),
+            path_style: self.path_style.unwrap_or(default.path_style),
+            connect_timeout: self.connect_timeout.unwrap_or(default.connect_timeout),
+            request_timeout: match self.request_timeout {
+                Some(limit) if limit.is_zero() => None,
+                Some(limit) => Some(limit),
+                None => default.request_timeout,
+            },
+            retry: RetryPolicy {
+                attempts: self.retries.unwrap_or(default.retry.attempts),
+                delay: self.retry_delay.unwrap_or(default.retry.delay),
+            },
+        }
+    }
+}
+
+/// fingerprint fields to claim in the handshake; anything left out comes from the profile, then the captured install
+#[derive(Debug, Default, clap::Args)]
+pub struct FingerprintArgs {
+    #[arg(long)]
+    pub username: Option<String>,
+    #[arg(long = "implant-version")]
+    pub version: Option<String>,
+    #[arg(long)]
+    pub os: Option<String>,
+    /// unix timestamp or `now`
+    #[arg(long, value_parser = parse_timestamp)]
+    pub timestamp: Option<u64>,
+    /// extra `key=value` field, repeatable
+    #[arg(long = "fingerprint-field", value_parser = parse_field)]
+    pub extra: Vec<(String, String)>,
+}
+
+/// the flags every binary takes to reach a server and handshake with it
+#[derive(Debug, Default, clap::Args)]
+pub struct ConnectArgs {
+    /// toml file with the client settings; flags win over it
+    #[arg(long)]
+    pub profile: Option<PathBuf>,
+    /// `host:port` of the server [default: 127.0.0.1:6666]
+    #[arg(long)]
+    pub server: Option<String>,
+    /// hex, dashes allowed
+    #[arg(long, value_parser = parse_uuid)]
+    pub uuid: Option<[u8; 16]>,
+    /// hex of the server's public key, the handshake is sealed to it
+    #[arg(long, value_parser = parse_public_key)]
+    pub server_key: Option<PublicKey>,
+    /// hex of the session key, instead of deriving it from the fingerprint
+    #[arg(long, value_parser = parse_key)]
+    pub key: Option<Key>,
+    /// how the session key is derived from the fingerprint, e.g. `{username}+{version_number}+{timestamp}`
+    #[arg(long)]
+    pub key_format: Option<KeyFormat>,
+    #[command(flatten)]
+    pub fingerprint: FingerprintArgs,
+}
+
+impl ConnectArgs {
+    /// the flags, with anything they leave out taken from `--profile`
+    pub fn into_profile(self) -> Result<Profile, ConfigError> {
+        let file = self.profile.map(|x| Profile::load(&x)).transpose()?.unwrap_or_default();
+        let fingerprint = self.fingerprint;
+        let flags = Profile {
+            server: self.server,
+            uuid: self.uuid,
+            server_key: self.server_key,
+            key: self.key,
+            key_format: self.key_format,
+            fingerprint: FingerprintProfile {
+                username: fingerprint.username,
+                version: fingerprint.version,
+                os: fingerprint.os,
+                timestamp: fingerprint.timestamp,
+                extra: fingerprint.extra,
+            },
+            ..Profile::default()
+        };
+        Ok(flags.or(file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn test_profile() {
+        let profile: Profile = toml::from_str(
+            r#"
+            server = "10.0.0.2:7777"
+            uuid = "c2cd31ed-2713-4010-a0de-dfc817a341b7"
+            key_format = "{username}+{timestamp}"
+            request_timeout = 0
+            connect_timeout = 2.5
+            retries = 0
+            log_max_bytes = 1000000
+            path_style = "windows"
+
+            [fingerprint]
+            username = "bob"
+            timestamp = 1634050100
+            extra = ["host=box"]
+            "#,
+        )
+        .unwrap();
+        let cli = Profile { server: Some("10.0.0.3:6666".to_string()), ..Profile::default() };
+        let config = cli.or(profile).into_config();
+
+        assert_eq!(config.server, "10.0.0.3:6666");
+        assert_eq!(config.uuid, hex!("c2cd31ed27134010a0dedfc817a341b7"));
+        assert_eq!(config.server_key, SERVER_KEY);
+        assert_eq!(config.fingerprint.username, "bob");
+        assert_eq!(config.fingerprint.version, "2.1.3.0-PQF");
+        assert_eq!(config.fingerprint.extra, [("host".to_string(), "box".to_string())]);
+        assert_eq!(config.session_key().unwrap(), session::hash_key("bob+1634050100"));
+        assert_eq!(config.download_dir, PathBuf::from("received"));
+        assert_eq!(config.path_style, PathStyle::Windows);
+        assert_eq!(config.connect_timeout, Duration::from_millis(2500));
+        assert_eq!(config.request_timeout, None);
+        assert_eq!(config.retry, RetryPolicy { attempts: 0, ..RetryPolicy::default() });
+        assert_eq!(config.log_rotation, Rotation { max_bytes: Some(1000000), keep: 5 });
+    }
+
+    #[test]
+    fn test_profile_errors() {
+        assert!(toml::from_str::<Profile>(r#"uuid = "0011""#).is_err());
+        assert!(toml::from_str::<Profile>(r#"key_format = "{username""#).is_err());
+        assert!(toml::from_str::<Profile>(r#"path_style = "dos""#).is_err());
+        assert!(toml::from_str::<Profile>(r#"sever = "typo:6666""#).is_err());
+        let profile: Profile = toml::from_str("[fingerprint]\ntimestamp = \"now\"").unwrap();
+        assert!(profile.fingerprint.timestamp.unwrap() > 1634050056);
+    }
+
+    #[test]
+    fn test_connect_args() {
+        use clap::Parser;
+
+        #[derive(Parser)]
+        struct Args {
+            #[command(flatten)]
+            connect: ConnectArgs,
+        }
+
+        let path = std::env::temp_dir().join(format!("client-profile-{}.toml", std::process::id()));
+        std::fs::write(&path, "server = \"10.0.0.2:7777\"\nkey_format = \"{username}\"\n[fingerprint]\nusername = \"bob\"\nos = \"linux\"\n").unwrap();
+        let args = Args::parse_from([
+            "test".as_ref(),
+            "--profile".as_ref(),
+            path.as_os_str(),
+            "--uuid".as_ref(),
+            "c2cd31ed-2713-4010-a0de-dfc817a341b7".as_ref(),
+            "--username".as_ref(),
+            "alice".as_ref(),
+            "--fingerprint-field".as_ref(),
+            "host=box".as_ref(),
+        ]);
+        let config = args.connect.into_profile().unwrap().into_config();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.server, "10.0.0.2:7777");
+        assert_eq!(config.uuid, hex!("c2cd31ed27134010a0dedfc817a341b7"));
+        assert_eq!(config.fingerprint.username, "alice");
+        assert_eq!(config.fingerprint.os, "linux");
+        assert_eq!(config.fingerprint.extra, [("host".to_string(), "box".to_string())]);
+        assert_eq!(config.session_key().unwrap(), session::hash_key("alice"));
+
+        let missing = ConnectArgs { profile: Some(path), ..ConnectArgs::default() };
+        assert!(matches!(missing.into_profile(), Err(ConfigError::Io(..))));
+    }
+}
@@ -0,0 +1,16 @@
+use hex_literal::hex;
+use session::PublicKey;
+
+mod client;
+pub mod log;
+pub mod config;
+pub mod replay;
+mod transport;
+
+pub use client::Client;
+pub use config::{Config, Profile, RetryPolicy};
+pub use transport::Transport;
+pub use session;
+
+pub const SERVER_KEY: PublicKey = PublicKey(hex!("e8f1fbc853bdd630b7a2eda38c3100fcbe51227748ea9a6d73d5c18b846fb738"));
+pub const UUID: [u8; 16] = hex!("000102030405060708090a0b0c0d0f10");
@@ -0,0 +1,179 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use protocol::{parse, Block, Command};
+use serde::{Deserialize, Serialize};
+use session::frame::MAC_LEN;
+
+/// whether a logged message went out or came back
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Request,
+    Response,
+}
+
+/// one line of the log
+#[derive(Debug, Serialize)]
+pub struct Entry {
+    /// seconds since the epoch
+    pub timestamp: f64,
+    pub direction: Direction,
+    pub command: Option<Command>,
+    /// hex
+    pub plaintext: String,
+    /// the secretbox ciphertext, without the length header and nonce
+    pub ciphertext_len: usize,
+    /// `None` if the plaintext doesn't parse
+    pub blocks: Option<Vec<Block>>,
+}
+
+impl Entry {
+    pub fn new(direction: Direction, plaintext: &[u8]) -> Self {
+        let message = parse(plaintext).ok().map(|(_, message)| message);
+        Entry {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_secs_f64()).unwrap_or_default(),
+            direction,
+            command: message.as_ref().and_then(|x| x.command().cloned()),
+            plaintext: hex::encode(plaintext),
+            ciphertext_len: plaintext.len() + MAC_LEN,
+            blocks: message.map(|x| x.into_blocks()),
+        }
+    }
+}
+
+/// when to start a new log file
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Rotation {
+    /// `None` lets the log grow forever
+    pub max_bytes: Option<u64>,
+    /// old logs to hang on to, as `<path>.1` (the newest) to `<path>.<keep>`
+    pub keep: usize,
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Rotation { max_bytes: None, keep: 5 }
+    }
+}
+
+/// every request and response as a line of json, appended to a file
+#[derive(Debug)]
+pub struct SessionLog {
+    path: PathBuf,
+    rotation: Rotation,
+    file: File,
+    size: u64,
+}
+
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+impl SessionLog {
+    /// appends to `path`, making it if needed
+    pub fn open(path: &Path, rotation: Rotation) -> io::Result<Self> {
+        let file = open(path)?;
+        let size = file.metadata()?.len();
+        Ok(SessionLog { path: path.to_path_buf(), rotation, file, size })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn write(&mut self, entry: &Entry) -> io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        if let Some(max_bytes) = self.rotation.max_bytes {
+            if self.size > 0 && self.size + line.len() as u64 > max_bytes {
+                self.rotate()?;
+            }
+        }
+        self.file.write_all(&line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// logs `plaintext` going `direction`
+    pub fn record(&mut self, direction: Direction, plaintext: &[u8]) -> io::Result<()> {
+        self.write(&Entry::new(direction, plaintext))
+    }
+
+    // shifts every old log up one and starts the current one over
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.rotation.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(rotated(&self.path, self.rotation.keep));
+            for n in (1..self.rotation.keep).rev() {
+                match fs::rename(rotated(&self.path, n), rotated(&self.path, n + 1)) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                }
+            }
+            fs::rename(&self.path, rotated(&self.path, 1))?;
+        }
+        self.file = open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::{message, Message};
+
+    const UUID: [u8; 16] = protocol::macros::uuid("000102030405060708090a0b0c0d0f10");
+
+    #[test]
+    fn test_entry() {
+        let request = Message::make_init(UUID);
+        let entry = serde_json::to_value(Entry::new(Direction::Request, request.as_bytes())).unwrap();
+        assert_eq!(entry["direction"], "request");
+        assert_eq!(entry["command"], "Init");
+        assert_eq!(entry["plaintext"], hex::encode(request.as_bytes()));
+        assert_eq!(entry["ciphertext_len"], request.as_bytes().len() + 16);
+        assert_eq!(entry["blocks"][2]["Param"]["Uuid"], "000102030405060708090a0b0c0d0f10");
+
+        let entry = serde_json::to_value(Entry::new(Direction::Response, b"junk")).unwrap();
+        assert!(entry["command"].is_null() && entry["blocks"].is_null());
+    }
+
+    #[test]
+    fn test_rotation() {
+        let dir = std::env::temp_dir().join(format!("client-log-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("session.jsonl");
+        let line = serde_json::to_vec(&Entry::new(Direction::Request, message! { cmd: Fin, uuid: UUID }.as_bytes())).unwrap().len() as u64 + 1;
+        // timestamps aren't all the same length
+        let max_bytes = Some(line * 2 + line / 2);
+
+        // two lines to a file, two old files
+        let mut log = SessionLog::open(&path, Rotation { max_bytes, keep: 2 }).unwrap();
+        for _ in 0..7 {
+            log.record(Direction::Request, message! { cmd: Fin, uuid: UUID }.as_bytes()).unwrap();
+        }
+        let lines = |path: &Path| fs::read_to_string(path).map(|x| x.lines().count()).unwrap_or(0);
+        assert_eq!(lines(&path), 1);
+        assert_eq!(lines(&rotated(&path, 1)), 2);
+        assert_eq!(lines(&rotated(&path, 2)), 2);
+        assert!(!rotated(&path, 3).exists());
+
+        // picks up the size of what's there
+        let mut log = SessionLog::open(&path, Rotation { max_bytes, keep: 0 }).unwrap();
+        log.record(Direction::Request, message! { cmd: Fin, uuid: UUID }.as_bytes()).unwrap();
+        log.record(Direction::Request, message! { cmd: Fin, uuid: UUID }.as_bytes()).unwrap();
+        assert_eq!(lines(&path), 1);
+    }
+}
@@ -0,0 +1,118 @@
+use protocol::{parse, Block};
+use serde::Deserialize;
+
+use crate::log::Direction;
+
+/// a request from a recording, and what came back for it if that was recorded too
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Exchange {
+    pub request: Vec<u8>,
+    pub response: Option<Vec<u8>>,
+}
+
+// the parts of a session log line that matter here
+#[derive(Deserialize)]
+struct Logged {
+    direction: Direction,
+    plaintext: String,
+}
+
+/// reads a recording: `> ` lines are plaintext requests in hex, and a `< ` line is the response to
+/// the request before it. lines of a [`SessionLog`](crate::log::SessionLog) work too. blank lines
+/// and `#` comments are skipped
+pub fn read_recording(text: &str) -> Result<Vec<Exchange>, String> {
+    let mut exchanges: Vec<Exchange> = Vec::new();
+    for (number, line) in text.lines().enumerate().map(|(i, x)| (i + 1, x.trim())) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (direction, plaintext) = if line.starts_with('{') {
+            let logged: Logged = serde_json::from_str(line).map_err(|e| format!("line {}: {}", number, e))?;
+            (Some(if logged.direction == Direction::Request { '>' } else { '<' }), logged.plaintext)
+        } else {
+            let mut chars = line.chars();
+            (chars.next(), chars.as_str().trim().to_string())
+        };
+        let plaintext = hex::decode(plaintext).map_err(|e| format!("line {}: {}", number, e))?;
+        match (direction, exchanges.last_mut()) {
+            (Some('>'), _) => exchanges.push(Exchange { request: plaintext, response: None }),
+            (Some('<'), Some(last)) if last.response.is_none() => last.response = Some(plaintext),
+            (Some('<'), _) => return Err(format!("line {}: a response with no request before it", number)),
+            _ => return Err(format!("line {}: needs to start with `>` or `<`", number)),
+        }
+    }
+    Ok(exchanges)
+}
+
+fn blocks(plaintext: &[u8]) -> Option<Vec<Block>> {
+    parse(plaintext).ok().map(|(_, message)| message.into_blocks())
+}
+
+/// how `got` differs from the recorded `expected`, a line per block; empty if they're the same
+pub fn diff(expected: &[u8], got: &[u8]) -> Vec<String> {
+    if expected == got {
+        return Vec::new();
+    }
+    let (Some(expected), Some(got)) = (blocks(expected), blocks(got)) else {
+        return vec![format!("expected {}, got {}", hex::encode(expected), hex::encode(got))];
+    };
+    (0..expected.len().max(got.len()))
+        .filter(|&i| expected.get(i) != got.get(i))
+        .map(|i| match (expected.get(i), got.get(i)) {
+            (Some(expected), Some(got)) => format!("block {}: expected {:x?}, got {:x?}", i, expected, got),
+            (Some(expected), None) => format!("block {}: expected {:x?}, got nothing", i, expected),
+            (None, got) => format!("block {}: didn't expect {:x?}", i, got.unwrap()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log::Entry;
+    use protocol::message;
+
+    #[test]
+    fn test_read_recording() {
+        let text = "# a comment\n> 0102\n< 0304\n\n>0506\n";
+        let exchanges = read_recording(text).unwrap();
+        assert_eq!(
+            exchanges,
+            [
+                Exchange { request: vec![1, 2], response: Some(vec![3, 4]) },
+                Exchange { request: vec![5, 6], response: None },
+            ]
+        );
+        assert!(read_recording("< 0102").is_err());
+        assert!(read_recording("> 01\n< 02\n< 03").is_err());
+        assert!(read_recording("> zz").is_err());
+        assert!(read_recording("? 01").is_err());
+        assert!(read_recording("é").is_err());
+    }
+
+    #[test]
+    fn test_read_log() {
+        let request = serde_json::to_string(&Entry::new(Direction::Request, &[1, 2])).unwrap();
+        let response = serde_json::to_string(&Entry::new(Direction::Response, &[3, 4])).unwrap();
+        let exchanges = read_recording(&format!("{}\n{}\n", request, response)).unwrap();
+        assert_eq!(exchanges, [Exchange { request: vec![1, 2], response: Some(vec![3, 4]) }]);
+        assert!(read_recording(r#"{"direction": "sideways", "plaintext": ""}"#).is_err());
+    }
+
+    #[test]
+    fn test_diff() {
+        let expected = message! { cmd: ListDir, folder: "a", folder: "b", code: 0 };
+        assert!(diff(expected.as_bytes(), expected.as_bytes()).is_empty());
+
+        let got = message! { cmd: ListDir, folder: "a", folder: "c", code: 0 };
+        let lines = diff(expected.as_bytes(), got.as_bytes());
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("block 3: "));
+
+        // shorter, so every block from the first difference on is off
+        let got = message! { cmd: ListDir, code: 0 };
+        assert_eq!(diff(expected.as_bytes(), got.as_bytes()).len(), 4);
+
+        assert_eq!(diff(expected.as_bytes(), b"junk").len(), 1);
+    }
+}
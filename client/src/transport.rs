@@ -0,0 +1,195 @@
+use protocol::{parse, ParsedMessage};
+use session::{decrypt, encrypt, make_handshake, Error, Fingerprint, FrameDecoder, Key, PublicKey, Result};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+use crate::Config;
+
+/// [`session::Session`] on tokio, safe to drop a request halfway through
+///
+/// there are no request ids, responses just come back in order. so a request that gets cancelled or
+/// times out still has its response on the way; it's read and thrown away before the next one
+#[derive(Debug)]
+pub struct Transport<S> {
+    stream: S,
+    key: Key,
+    decoder: FrameDecoder,
+    // a frame not fully written yet, and how much of it is out
+    outbox: Vec<u8>,
+    written: usize,
+    // responses still to come, the last of them for the current request
+    awaiting: usize,
+}
+
+impl Transport<TcpStream> {
+    /// tcp connection and handshake to `config.server`, under the connect timeout; nothing else
+    /// is sent
+    pub async fn connect(config: &Config) -> Result<Self> {
+        let key = config.session_key()?;
+        let connect = async {
+            let stream = TcpStream::connect(&config.server).await?;
+            Transport::handshake(stream, &config.fingerprint, &config.server_key, key).await
+        };
+        timeout(config.connect_timeout, connect).await.map_err(|_| Error::Timeout)?
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Transport<S> {
+    /// for a stream that's already past the handshake
+    pub fn new(stream: S, key: Key) -> Self {
+        Transport { stream, key, decoder: FrameDecoder::new(), outbox: Vec::new(), written: 0, awaiting: 0 }
+    }
+
+    /// sends the handshake for `fingerprint`; `key` is usually [`Fingerprint::session_key`]
+    pub async fn handshake(mut stream: S, fingerprint: &Fingerprint, server_public_key: &PublicKey, key: Key) -> Result<Self> {
+        stream.write_all(&make_handshake(fingerprint, server_public_key)).await?;
+        Ok(Self::new(stream, key))
+    }
+
+    pub fn key(&self) -> &Key {
+        &self.key
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// responses that haven't come back yet; between requests, these are all for cancelled ones
+    pub fn pending(&self) -> usize {
+        self.awaiting
+    }
+
+    // picks up where a cancelled write left off; the server can't make sense of half a frame
+    async fn flush(&mut self) -> Result<()> {
+        while self.written < self.outbox.len() {
+            let written = self.stream.write(&self.outbox[self.written..]).await?;
+            if written == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::WriteZero).into());
+            }
+            self.written += written;
+        }
+        self.outbox.clear();
+        self.written = 0;
+        Ok(())
+    }
+
+    async fn read_frame(&mut self) -> Result<Vec<u8>> {
+        let mut buf = [0u8; 0x1000];
+        loop {
+            match self.decoder.next_frame() {
+                Ok(Some(frame)) => return Ok(frame),
+                Ok(None) => {}
+                // what's buffered can't be lined back up with frames, and neither can the responses
+                // still owed
+                Err(e) => {
+                    self.decoder = FrameDecoder::new();
+                    self.awaiting = 0;
+                    return Err(e);
+                }
+            }
+            match self.stream.read(&mut buf).await? {
+                0 => return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()),
+                read => self.decoder.push(&buf[..read]),
+            }
+        }
+    }
+
+    /// sends one message and waits for the plaintext of the response to it
+    ///
+    /// cancel safe: if the future is dropped, the next request finishes sending this one and skips
+    /// its response
+    pub async fn request_raw(&mut self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        self.flush().await?;
+        self.outbox = encrypt(&self.key, plaintext.to_vec())?;
+        self.awaiting += 1;
+        self.flush().await?;
+        loop {
+            let frame = self.read_frame().await?;
+            self.awaiting -= 1;
+            if self.awaiting > 0 {
+                continue;
+            }
+            return decrypt(&self.key, &frame);
+        }
+    }
+
+    /// [`Transport::request_raw`], parsed
+    pub async fn request(&mut self, plaintext: &[u8]) -> Result<ParsedMessage> {
+        let response = self.request_raw(plaintext).await?;
+        match parse(&response) {
+            Ok((_, message)) => Ok(message),
+            Err(_) => Err(Error::Parse),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+    use protocol::{message, Protocol};
+    use session::frame::read_handshake;
+    use session::{KeyFormat, Session};
+    use std::time::Duration;
+
+    // answers each request with its own bytes, after `delay`
+    fn echo(stream: std::net::TcpStream, key: Key, delay: Duration) {
+        let mut stream = stream;
+        read_handshake(&mut stream).unwrap();
+        let mut session = Session::new(stream, key);
+        while let Ok(request) = session.recv() {
+            std::thread::sleep(delay);
+            session.send(&request).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel() {
+        let key = Fingerprint::default().session_key(&KeyFormat::default()).unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_key = key.clone();
+        std::thread::spawn(move || echo(listener.accept().unwrap().0, server_key, Duration::from_millis(200)));
+
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let mut transport = Transport::handshake(stream, &Fingerprint::default(), &PublicKey([0; 32]), key).await.unwrap();
+        let first = message! { cmd: Init, uuid: hex!("000102030405060708090a0b0c0d0f10") };
+        let second = message! { cmd: Fin, uuid: hex!("000102030405060708090a0b0c0d0f10") };
+
+        let cancelled = tokio::time::timeout(Duration::from_millis(50), transport.request(first.as_bytes())).await;
+        assert!(cancelled.is_err());
+        assert_eq!(transport.pending(), 1);
+        // the echo of the first request comes back first and gets skipped
+        let response = transport.request(second.as_bytes()).await.unwrap();
+        assert_eq!(response, parse(&second.to_proto_bytes()).unwrap().1);
+        assert_eq!(transport.pending(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_desync() {
+        let key = Fingerprint::default().session_key(&KeyFormat::default()).unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_key = key.clone();
+        // a garbage length header for the first request, then a proper echo
+        std::thread::spawn(move || {
+            let mut stream = listener.accept().unwrap().0;
+            read_handshake(&mut stream).unwrap();
+            let mut session = Session::new(stream, server_key);
+            session.recv().unwrap();
+            std::io::Write::write_all(&mut session.get_ref(), &[0xff; 4]).unwrap();
+            let request = session.recv().unwrap();
+            session.send(&request).unwrap();
+        });
+
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let mut transport = Transport::handshake(stream, &Fingerprint::default(), &PublicKey([0; 32]), key).await.unwrap();
+        let request = message! { cmd: Fin, uuid: hex!("000102030405060708090a0b0c0d0f10") };
+        assert!(matches!(transport.request(request.as_bytes()).await, Err(Error::LengthHeader(_))));
+        assert_eq!(transport.pending(), 0);
+        // starts over instead of choking on the same bytes forever
+        let response = transport.request(request.as_bytes()).await.unwrap();
+        assert_eq!(response, parse(&request.to_proto_bytes()).unwrap().1);
+    }
+}
@@ -0,0 +1,227 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use client::session::frame::MAX_PLAINTEXT_LEN;
+use client::session::{Error, Fingerprint};
+use client::{Client, Config, RetryPolicy};
+use mock_server::testing::{data, spawn, Spawned};
+use mock_server::{MAX_CONTENTS, NOT_FOUND, TOO_LARGE};
+use protocol::PathStyle;
+use sodiumoxide::crypto::box_;
+
+// passes connections through to the server, and can hang up on all of them or go quiet
+struct Relay {
+    addr: SocketAddr,
+    connections: Arc<Mutex<Vec<TcpStream>>>,
+    stalls: Arc<Mutex<Vec<Arc<AtomicBool>>>>,
+    accepted: Arc<Mutex<usize>>,
+}
+
+// copies until `from` closes, dropping what it reads once `stalled` is set
+fn forward(mut from: TcpStream, mut to: TcpStream, stalled: Arc<AtomicBool>) {
+    let mut buf = [0; 4096];
+    while let Ok(read @ 1..) = from.read(&mut buf) {
+        if !stalled.load(Ordering::SeqCst) && to.write_all(&buf[..read]).is_err() {
+            break;
+        }
+    }
+}
+
+fn relay(server: &Spawned) -> Relay {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let target = server.addr;
+    let connections = Arc::new(Mutex::new(Vec::new()));
+    let stalls = Arc::new(Mutex::new(Vec::new()));
+    let accepted = Arc::new(Mutex::new(0));
+    let (open, flags, count) = (connections.clone(), stalls.clone(), accepted.clone());
+    std::thread::spawn(move || {
+        for client in listener.incoming() {
+            let client = client.unwrap();
+            let upstream = TcpStream::connect(target).unwrap();
+            let (client_rx, upstream_tx) = (client.try_clone().unwrap(), upstream.try_clone().unwrap());
+            let (upstream_rx, client_tx) = (upstream.try_clone().unwrap(), client.try_clone().unwrap());
+            let stalled = Arc::new(AtomicBool::new(false));
+            open.lock().unwrap().extend([client, upstream]);
+            flags.lock().unwrap().push(stalled.clone());
+            *count.lock().unwrap() += 1;
+            let other = stalled.clone();
+            std::thread::spawn(move || forward(client_rx, upstream_tx, stalled));
+            std::thread::spawn(move || forward(upstream_rx, client_tx, other));
+        }
+    });
+    Relay { addr, connections, stalls, accepted }
+}
+
+impl Relay {
+    fn cut(&self) {
+        for stream in self.connections.lock().unwrap().drain(..) {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+
+    // sends the clients something that isn't a frame
+    fn garble(&self) {
+        // every client is followed by its upstream
+        for mut stream in self.connections.lock().unwrap().iter().step_by(2) {
+            let _ = stream.write_all(&[0xff; 4]);
+        }
+    }
+
+    // the connections stay up, but nothing gets through them any more
+    fn stall(&self) {
+        for stalled in self.stalls.lock().unwrap().drain(..) {
+            stalled.store(true, Ordering::SeqCst);
+        }
+    }
+
+    fn accepted(&self) -> usize {
+        *self.accepted.lock().unwrap()
+    }
+}
+
+fn config(server: &Spawned) -> Config {
+    Config {
+        server: server.addr.to_string(),
+        server_key: server.public_key,
+        fingerprint: Fingerprint { username: "bob".to_string(), ..Fingerprint::default() },
+        ..Config::default()
+    }
+}
+
+async fn connect(server: &Spawned) -> Client {
+    Client::connect(&config(server)).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_handshake() {
+    let server = spawn("client-handshake");
+    connect(&server).await.fin().await.unwrap();
+
+    // the server keys the session from the fingerprint it opened, so a client deriving the key
+    // some other way gets hung up on
+    let wrong_format = Config { key_format: "{username}+{timestamp}".parse().unwrap(), ..config(&server) };
+    assert!(matches!(Client::connect(&wrong_format).await, Err(Error::Io(_))));
+
+    // and so does one sealing the handshake to someone else
+    let wrong_key = Config { server_key: box_::gen_keypair().0, ..config(&server) };
+    assert!(matches!(Client::connect(&wrong_key).await, Err(Error::Io(_))));
+}
+
+#[tokio::test]
+async fn test_ls_cd_get() {
+    let server = spawn("client-get");
+    let data = data();
+    let mut client = connect(&server).await;
+    assert_eq!(client.ls().await.unwrap(), ["docs/"]);
+    client.cd("docs");
+    assert_eq!(client.cwd().to_string(), "/docs");
+    assert_eq!(client.ls().await.unwrap(), ["a.txt", "data.bin"]);
+    assert_eq!(client.get("data.bin").await.unwrap(), data);
+
+    assert!(matches!(client.get("nope").await, Err(Error::Code(NOT_FOUND))));
+    client.cd("..");
+    assert_eq!(client.get("docs/data.bin").await.unwrap(), data);
+    client.fin().await.unwrap();
+
+    let windows = Config { path_style: PathStyle::Windows, ..config(&server) };
+    let mut client = Client::connect(&windows).await.unwrap();
+    client.cd("C:/Users/../Windows");
+    assert_eq!(client.cwd().to_string(), "C:\\Windows");
+    client.fin().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_upload() {
+    let server = spawn("client-upload");
+    let data = data();
+    let mut client = connect(&server).await;
+    let folder = client.session_folder().await.unwrap();
+    client.cd(&folder.to_string());
+    client.upload("copy.bin", &data[..20000]).await.unwrap();
+    assert_eq!(client.ls().await.unwrap(), ["copy.bin"]);
+    assert_eq!(client.get("copy.bin").await.unwrap(), &data[..20000]);
+    assert_eq!(fs::read(server.root.join(folder.components().join("/")).join("copy.bin")).unwrap(), &data[..20000]);
+    client.fin().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_upload_large() {
+    let server = spawn("client-large");
+    let mut client = connect(&server).await;
+    let folder = client.session_folder().await.unwrap();
+    client.cd(&folder.to_string());
+
+    // fits in a frame, but is more than the server will send back in one
+    let big = (0..MAX_CONTENTS + 1).map(|x| x as u8).collect::<Vec<_>>();
+    client.upload("big.bin", &big).await.unwrap();
+    assert_eq!(fs::read(server.root.join(folder.components().join("/")).join("big.bin")).unwrap(), big);
+    assert!(matches!(client.get("big.bin").await, Err(Error::Code(TOO_LARGE))));
+
+    // doesn't fit at all; refused before anything is sent, and the session carries on
+    let huge = vec![0x41; MAX_PLAINTEXT_LEN + 1];
+    assert!(matches!(client.upload("huge.bin", &huge).await, Err(Error::TooLong(_))));
+    assert!(matches!(client.upload("huge.bin", &vec![0x41; 70000]).await, Err(Error::TooLong(_))));
+    assert_eq!(client.ls().await.unwrap(), ["big.bin"]);
+    client.fin().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_reconnect() {
+    let server = spawn("client-reconnect");
+    let data = data();
+    let relay = relay(&server);
+    let retry = RetryPolicy { attempts: 2, delay: Duration::from_millis(10) };
+    let mut client = Client::connect(&Config { server: relay.addr.to_string(), retry, ..config(&server) }).await.unwrap();
+    client.cd("docs");
+    assert_eq!(client.ls().await.unwrap(), ["a.txt", "data.bin"]);
+
+    // ls is retried on the new connection, from the same directory
+    relay.cut();
+    assert_eq!(client.ls().await.unwrap(), ["a.txt", "data.bin"]);
+    assert_eq!(relay.accepted(), 2);
+
+    // an upload isn't, but the session is back for the next request
+    relay.cut();
+    assert!(matches!(client.upload("copy.bin", &data[..10]).await, Err(Error::Io(_))));
+    assert_eq!(relay.accepted(), 3);
+    assert_eq!(client.get("data.bin").await.unwrap(), data);
+    client.fin().await.unwrap();
+
+    // and without retries the error just comes back
+    let retry = RetryPolicy { attempts: 0, ..retry };
+    let mut client = Client::connect(&Config { server: relay.addr.to_string(), retry, ..config(&server) }).await.unwrap();
+    relay.cut();
+    assert!(matches!(client.ls().await, Err(Error::Io(_))));
+    assert_eq!(relay.accepted(), 4);
+
+    // a connection that goes quiet instead of dropping
+    let retry = RetryPolicy { attempts: 2, ..retry };
+    let quiet = Config { server: relay.addr.to_string(), request_timeout: Some(Duration::from_millis(200)), retry, ..config(&server) };
+    let mut client = Client::connect(&quiet).await.unwrap();
+    client.cd("docs");
+
+    // a timed out ls is as good as a drop, so it's retried on a new connection
+    relay.stall();
+    assert_eq!(client.ls().await.unwrap(), ["a.txt", "data.bin"]);
+    assert_eq!(relay.accepted(), 6);
+
+    // one timed out upload might just be slow; the second in a row isn't
+    relay.stall();
+    assert!(matches!(client.upload("copy.bin", &data[..10]).await, Err(Error::Timeout)));
+    assert_eq!(relay.accepted(), 6);
+    assert!(matches!(client.upload("copy.bin", &data[..10]).await, Err(Error::Timeout)));
+    assert_eq!(relay.accepted(), 7);
+    assert_eq!(client.get("data.bin").await.unwrap(), data);
+
+    // a stream that's lost track of the frames is no better than a dropped one
+    relay.garble();
+    assert_eq!(client.ls().await.unwrap(), ["a.txt", "data.bin"]);
+    assert_eq!(relay.accepted(), 8);
+    assert_eq!(client.ls().await.unwrap(), ["a.txt", "data.bin"]);
+    client.fin().await.unwrap();
+}
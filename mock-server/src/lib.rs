@@ -0,0 +1,223 @@
+use std::fs;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+
+use protocol::*;
+use session::frame::read_handshake;
+use session::{open_handshake, Error, Fingerprint, KeyFormat, Result, SecretKey, Session};
+
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+pub const OK: u32 = 0;
+/// the file or directory couldn't be read or written
+pub const FAILED: u32 = 1;
+pub const NOT_FOUND: u32 = 2;
+/// unknown command, missing params or a path outside the root
+pub const BAD_REQUEST: u32 = 3;
+/// the file doesn't fit in one response
+pub const TOO_LARGE: u32 = 4;
+
+/// the most file contents sent in one response; leaves room for the rest of the message in a frame
+pub const MAX_CONTENTS: usize = 0xF000;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// served as `/`
+    pub root: PathBuf,
+    pub secret_key: SecretKey,
+    pub key_format: KeyFormat,
+    /// used when a handshake doesn't open with `secret_key`, e.g. from a client sealing to the real server
+    pub fallback: Option<Fingerprint>,
+}
+
+/// accepts connections forever, one thread each
+pub fn serve(listener: TcpListener, config: Config) -> io::Result<()> {
+    session::serve(listener, move |stream| handle_connection(stream, &config))
+}
+
+/// the handshake, then requests until the client sends Fin or hangs up
+pub fn handle_connection(mut stream: TcpStream, config: &Config) -> Result<()> {
+    let handshake = read_handshake(&mut stream)?;
+    let fingerprint = match open_handshake(&handshake, &config.secret_key) {
+        Ok((fingerprint, _)) => fingerprint,
+        Err(Error::Handshake) if config.fallback.is_some() => config.fallback.clone().unwrap(),
+        Err(e) => return Err(e),
+    };
+    eprintln!("handshake from {} {} ({})", fingerprint.username, fingerprint.version, fingerprint.timestamp);
+    let key = fingerprint.session_key(&config.key_format)?;
+    let mut session = Session::new(stream, key);
+
+    loop {
+        let plaintext = match session.recv() {
+            Ok(plaintext) => plaintext,
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let (response, fin) = match parse(&plaintext) {
+            Ok((_, request)) => (respond(&config.root, &request), request.command() == Some(&Command::Fin)),
+            Err(_) => (bad_request(), false),
+        };
+        session.send(response.as_bytes())?;
+        if fin {
+            return Ok(());
+        }
+    }
+}
+
+// where a remote path lives under the root; relative paths are taken from the root, and can't climb out of it
+fn local_path(root: &Path, remote: &RemotePath) -> Option<PathBuf> {
+    if remote.components().first().map(String::as_str) == Some("..") {
+        return None;
+    }
+    Some(remote.components().iter().fold(root.to_path_buf(), |path, x| path.join(x)))
+}
+
+// there's no command to answer with, so it's just the code
+fn bad_request() -> Message {
+    Message::new().append(Magic::Start).append(Param::Code(BAD_REQUEST)).append(Magic::End).build()
+}
+
+fn status(e: &io::Error) -> u32 {
+    match e.kind() {
+        io::ErrorKind::NotFound => NOT_FOUND,
+        _ => FAILED,
+    }
+}
+
+/// the response to one request, served from `root`
+pub fn respond(root: &Path, request: &ParsedMessage) -> Message {
+    let mut builder = match request.command() {
+        Some(cmd) => MessageBuilder::new().cmd(cmd.clone()),
+        None => return bad_request(),
+    };
+    if let Some(uuid) = request.uuid() {
+        builder = builder.uuid(*uuid);
+    }
+    let dir = request.get(ParamKind::DirName).and_then(Param::as_str).map(RemotePath::posix);
+    let file = request.get(ParamKind::FileName).and_then(Param::as_str);
+
+    match request.command().unwrap() {
+        Command::Init | Command::Fin => builder.code(OK).finish(),
+        Command::GetSessionFolder => {
+            let uuid = match request.uuid() {
+                Some(uuid) => hex::encode(uuid),
+                None => return builder.code(BAD_REQUEST).finish(),
+            };
+            match fs::create_dir_all(root.join(&uuid)) {
+                Ok(()) => builder.dir(format!("/{}", uuid)).code(OK).finish(),
+                Err(e) => builder.code(status(&e)).finish(),
+            }
+        }
+        Command::ListDir => {
+            let (dir, path) = match dir.and_then(|dir| local_path(root, &dir).map(|path| (dir, path))) {
+                Some(found) => found,
+                None => return builder.code(BAD_REQUEST).finish(),
+            };
+            let entries = match fs::read_dir(path) {
+                Ok(entries) => entries,
+                Err(e) => return builder.code(status(&e)).finish(),
+            };
+            // directories get a trailing `/` so they can be told apart
+            let mut names = entries
+                .filter_map(|x| x.ok())
+                .map(|x| {
+                    let name = x.file_name().to_string_lossy().into_owned();
+                    match x.file_type() {
+                        Ok(kind) if kind.is_dir() => name + "/",
+                        _ => name,
+                    }
+                })
+                .collect::<Vec<_>>();
+            names.sort();
+            names.into_iter().fold(builder.dir(dir.to_string()), |builder, x| builder.folder(x)).code(OK).finish()
+        }
+        Command::ReadFile => {
+            let (file, path) = match (dir, file) {
+                (Some(dir), Some(file)) => match local_path(root, &dir.join(file)) {
+                    Some(path) => (file, path),
+                    None => return builder.code(BAD_REQUEST).finish(),
+                },
+                _ => return builder.code(BAD_REQUEST).finish(),
+            };
+            match fs::read(path) {
+                Ok(contents) if contents.len() > MAX_CONTENTS => builder.file(file).code(TOO_LARGE).finish(),
+                Ok(contents) => builder.file(file).contents(contents).code(OK).finish(),
+                Err(e) => builder.code(status(&e)).finish(),
+            }
+        }
+        Command::Upload => {
+            let path = match (dir, file) {
+                (Some(dir), Some(file)) => match local_path(root, &dir.join(file)) {
+                    Some(path) => path,
+                    None => return builder.code(BAD_REQUEST).finish(),
+                },
+                _ => return builder.code(BAD_REQUEST).finish(),
+            };
+            let contents = request.get_all(ParamKind::Contents).filter_map(Param::as_bytes).flatten().copied().collect::<Vec<_>>();
+            match fs::write(path, contents) {
+                Ok(()) => builder.code(OK).finish(),
+                Err(e) => builder.code(status(&e)).finish(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture;
+    use hex_literal::hex;
+
+    const UUID: [u8; 16] = hex!("000102030405060708090a0b0c0d0f10");
+
+    fn request(message: Message) -> ParsedMessage {
+        parse(message.as_bytes()).unwrap().1
+    }
+
+    fn exchange(root: &Path, message: Message) -> ParsedMessage {
+        request(respond(root, &request(message)))
+    }
+
+    #[test]
+    fn test_list_dir() {
+        let root = fixture("ls");
+        let response = exchange(&root, Message::make_list_dir(UUID, &RemotePath::posix("/")));
+        assert_eq!(response.command(), Some(&Command::ListDir));
+        assert_eq!(response.code(), Some(OK));
+        assert_eq!(response.get_all(ParamKind::FolderContents).filter_map(Param::as_str).collect::<Vec<_>>(), ["docs/"]);
+
+        let response = exchange(&root, Message::make_list_dir(UUID, &RemotePath::posix("/nope")));
+        assert_eq!(response.code(), Some(NOT_FOUND));
+        let response = exchange(&root, Message::make_list_dir(UUID, &RemotePath::posix("../..")));
+        assert_eq!(response.code(), Some(BAD_REQUEST));
+    }
+
+    #[test]
+    fn test_read_upload() {
+        let root = fixture("files");
+        let response = exchange(&root, Message::make_read_file(UUID, &RemotePath::posix("/docs"), "a.txt"));
+        assert_eq!(response.code(), Some(OK));
+        assert_eq!(response.get(ParamKind::Contents).and_then(Param::as_bytes), Some(&b"hello"[..]));
+
+        let upload = message! { cmd: Upload, uuid: UUID, dir: "/docs", file: "b.txt", contents: b"there".to_vec() };
+        assert_eq!(exchange(&root, upload).code(), Some(OK));
+        assert_eq!(fs::read(root.join("docs/b.txt")).unwrap(), b"there");
+
+        // `..` is clamped at the root
+        let response = exchange(&root, Message::make_read_file(UUID, &RemotePath::posix("/../../docs"), "a.txt"));
+        assert_eq!(response.code(), Some(OK));
+        fs::write(root.join("big"), vec![0; MAX_CONTENTS + 1]).unwrap();
+        let response = exchange(&root, Message::make_read_file(UUID, &RemotePath::posix("/"), "big"));
+        assert_eq!(response.code(), Some(TOO_LARGE));
+    }
+
+    #[test]
+    fn test_session_folder() {
+        let root = fixture("session");
+        let response = exchange(&root, message! { cmd: GetSessionFolder, uuid: UUID });
+        assert_eq!(response.get(ParamKind::DirName).and_then(Param::as_str), Some("/000102030405060708090a0b0c0d0f10"));
+        assert!(root.join("000102030405060708090a0b0c0d0f10").is_dir());
+    }
+}
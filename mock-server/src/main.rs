@@ -0,0 +1,49 @@
+use clap::Parser;
+use mock_server::{serve, Config};
+use session::{parse_secret_key, Fingerprint, KeyFormat, SecretKey};
+use sodiumoxide::crypto::box_;
+use std::net::TcpListener;
+use std::path::PathBuf;
+
+// stands in for the listening post, serving a local directory
+#[derive(Debug, Parser)]
+struct Args {
+    /// directory served as `/`
+    #[arg(default_value = ".")]
+    root: PathBuf,
+    #[arg(long, default_value = "127.0.0.1:6666")]
+    listen: String,
+    /// hex of the server's box secret key; a fresh keypair is made if left out
+    #[arg(long, value_parser = parse_secret_key)]
+    secret_key: Option<SecretKey>,
+    #[arg(long, default_value_t = KeyFormat::default())]
+    key_format: KeyFormat,
+    /// key sessions from the default fingerprint when a handshake doesn't open with our key
+    #[arg(long)]
+    fallback: bool,
+}
+
+fn main() {
+    let args = Args::parse();
+    let secret_key = args.secret_key.unwrap_or_else(|| box_::gen_keypair().1);
+    let listener = match TcpListener::bind(&args.listen) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("can't listen on {}: {}", args.listen, e);
+            std::process::exit(1);
+        }
+    };
+    eprintln!("serving {} on {}", args.root.display(), args.listen);
+    eprintln!("public key {}", hex::encode(secret_key.public_key()));
+
+    let config = Config {
+        root: args.root,
+        secret_key,
+        key_format: args.key_format,
+        fallback: args.fallback.then(Fingerprint::default),
+    };
+    if let Err(e) = serve(listener, config) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
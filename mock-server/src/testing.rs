@@ -0,0 +1,43 @@
+//! a mock server to run tests against; behind the `testing` feature
+
+use std::fs;
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+
+use session::{KeyFormat, PublicKey};
+
+use crate::{serve, Config};
+
+/// what's in `docs/data.bin`: every byte value, and big enough to span several reads
+pub fn data() -> Vec<u8> {
+    (0..40000).map(|x| (x % 251) as u8).collect()
+}
+
+/// a fresh directory under the temp dir: `docs/a.txt` says hello, and `docs/data.bin` is [`data`]
+pub fn fixture(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("mock-server-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("docs")).unwrap();
+    fs::write(root.join("docs/a.txt"), b"hello").unwrap();
+    fs::write(root.join("docs/data.bin"), data()).unwrap();
+    root
+}
+
+/// a server started by [`spawn`]
+#[derive(Debug, Clone)]
+pub struct Spawned {
+    pub addr: SocketAddr,
+    pub public_key: PublicKey,
+    pub root: PathBuf,
+}
+
+/// serves a new [`fixture`] on an ephemeral port with a keypair of its own, until the process exits
+pub fn spawn(name: &str) -> Spawned {
+    let root = fixture(name);
+    let (public_key, secret_key) = sodiumoxide::crypto::box_::gen_keypair();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let config = Config { root: root.clone(), secret_key, key_format: KeyFormat::default(), fallback: None };
+    std::thread::spawn(move || serve(listener, config));
+    Spawned { addr, public_key, root }
+}
@@ -1,18 +1,199 @@
+mod pcap;
+mod reassembly;
+
+use clap::{Parser, Subcommand};
 use protocol::parse;
-use std::io::{stdin, BufRead};
+use session::frame::HEADER_LEN;
+use session::{decode_length_header, decrypt, hash_key, open_handshake, parse_key, parse_secret_key, Fingerprint, Key, KeyFormat, SecretKey};
+use std::io::{self, stdin, stdout, BufRead, Write};
+use std::path::PathBuf;
 
-fn main() {
-    stdin().lock().lines().flat_map(|x| x.ok()).for_each(|x| {
-        match parse(&hex::decode(x).unwrap()) {
-            Ok((_, params)) => {
-                for i in params {
-                    println!("{:x?}", i);
-                }
+// reads hex from stdin, one message per line, and prints what's in it
+#[derive(Debug, Parser)]
+struct Args {
+    #[command(subcommand)]
+    mode: Option<Mode>,
+    /// with a key, each line is a whole encrypted frame instead of plaintext; pcap takes it too
+    #[command(flatten)]
+    key: KeyArgs,
+}
+
+// the session key, outright or as the string that gets hashed into it
+#[derive(Debug, clap::Args)]
+#[group(multiple = false)]
+struct KeyArgs {
+    /// hex of the session key
+    #[arg(long, global = true, value_parser = parse_key)]
+    key: Option<Key>,
+    /// the string the session key is hashed from, e.g. `sky+2.1.3.0+1634050056`
+    #[arg(long, global = true)]
+    key_input: Option<String>,
+}
+
+impl KeyArgs {
+    fn key(self) -> Option<Key> {
+        self.key.or_else(|| self.key_input.as_deref().map(hash_key))
+    }
+}
+
+#[derive(Debug, Subcommand)]
+enum Mode {
+    /// each line is a captured handshake, opened with the server's secret key
+    Handshake {
+        /// hex of the server's box secret key
+        #[arg(long, value_parser = parse_secret_key)]
+        secret_key: SecretKey,
+    },
+    /// every message to and from the server in a pcap or pcapng file
+    Pcap {
+        file: PathBuf,
+        /// the server's tcp port
+        #[arg(long, default_value_t = 6666)]
+        port: u16,
+        /// hex of the server's box secret key, to derive the session key from each handshake
+        #[arg(long, value_parser = parse_secret_key)]
+        secret_key: Option<SecretKey>,
+        #[arg(long, default_value_t = KeyFormat::default())]
+        key_format: KeyFormat,
+    },
+}
+
+fn print_blocks(out: &mut impl Write, plaintext: &[u8]) -> io::Result<()> {
+    match parse(plaintext) {
+        Ok((_, params)) => {
+            for i in params {
+                writeln!(out, "{:x?}", i)?;
             }
-            Err(e) => {
-                println!("{}", e);
+        }
+        Err(e) => {
+            writeln!(out, "{}", e)?;
+        }
+    }
+    Ok(())
+}
+
+/// checks, opens and prints a whole frame
+fn print_frame(out: &mut impl Write, line: &str, key: &Key) -> io::Result<()> {
+    let frame = match hex::decode(line.trim()) {
+        Ok(frame) => frame,
+        Err(e) => return writeln!(out, "not hex: {}", e),
+    };
+    let Some(header) = frame.get(..HEADER_LEN) else {
+        return writeln!(out, "frame is only {} bytes", frame.len());
+    };
+    match decode_length_header(header.try_into().unwrap()) {
+        Ok(len) if len as usize != frame.len() - HEADER_LEN => {
+            writeln!(out, "length header says {} bytes follow it, but {} do", len, frame.len() - HEADER_LEN)?
+        }
+        Ok(_) => {}
+        Err(e) => return writeln!(out, "{}", e),
+    }
+    match decrypt(key, &frame) {
+        Ok(plaintext) => print_blocks(out, &plaintext),
+        Err(session::Error::Decrypt) => writeln!(out, "MAC check failed: wrong key, or the frame is corrupt"),
+        Err(e) => writeln!(out, "{}", e),
+    }
+}
+
+/// prints the fingerprint in a handshake, if it opens
+fn print_handshake(out: &mut impl Write, handshake: &[u8], secret_key: &SecretKey) -> io::Result<Option<Fingerprint>> {
+    match open_handshake(handshake, secret_key) {
+        Ok((fingerprint, client_public_key)) => {
+            writeln!(out, "client key: {}", hex::encode(client_public_key))?;
+            for (key, value) in fingerprint.fields() {
+                writeln!(out, "{}: {}", key, value)?;
             }
+            Ok(Some(fingerprint))
         }
-        println!("\n");
-    })
+        Err(e) => {
+            writeln!(out, "{}", e)?;
+            Ok(None)
+        }
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    let key = args.key.key();
+    if let Some(Mode::Pcap { file, port, secret_key, key_format }) = args.mode {
+        let keys = pcap::Keys { key, secret_key, key_format };
+        if let Err(e) = pcap::dump(&file, port, &keys, &mut stdout().lock()) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let (Some(Mode::Handshake { .. }), Some(_)) = (&args.mode, &key) {
+        eprintln!("handshakes are opened with --secret-key, not a session key");
+        std::process::exit(2);
+    }
+    let mut out = stdout().lock();
+    for x in stdin().lock().lines().map_while(Result::ok) {
+        let result = match (&args.mode, &key) {
+            (None, None) => match hex::decode(x.trim()) {
+                Ok(plaintext) => print_blocks(&mut out, &plaintext),
+                Err(e) => writeln!(out, "not hex: {}", e),
+            },
+            (None, Some(key)) => print_frame(&mut out, &x, key),
+            (Some(Mode::Handshake { secret_key }), _) => match hex::decode(x.trim()) {
+                Ok(handshake) => print_handshake(&mut out, &handshake, secret_key).map(|_| ()),
+                Err(e) => writeln!(out, "not hex: {}", e),
+            },
+            (Some(Mode::Pcap { .. }), _) => unreachable!(),
+        };
+        // nowhere left to print to
+        if result.and_then(|_| writeln!(out, "\n")).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_anywhere() {
+        let key = "00".repeat(32);
+        for argv in [
+            vec!["parse-cli", "--key", &key],
+            vec!["parse-cli", "--key", &key, "pcap", "a.pcap"],
+            vec!["parse-cli", "pcap", "a.pcap", "--key", &key],
+        ] {
+            assert!(Args::try_parse_from(&argv).unwrap().key.key().is_some(), "{:?}", argv);
+        }
+        assert!(Args::try_parse_from(["parse-cli", "pcap", "a.pcap", "--key-input", "sky"]).unwrap().key.key().is_some());
+        assert!(Args::try_parse_from(["parse-cli", "--key", &key, "--key-input", "sky"]).is_err());
+    }
+
+    fn frame_output(line: &str, key: &Key) -> String {
+        let mut out = Vec::new();
+        print_frame(&mut out, line, key).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_print_frame() {
+        let key = hash_key("sky+2.1.3.0+1634050056");
+        let plaintext = protocol::message! { cmd: Init, uuid: "c2cd31ed27134010a0dedfc817a341b7" }.as_bytes().to_vec();
+        let frame = session::encrypt(&key, plaintext.clone()).unwrap();
+
+        let mut blocks = Vec::new();
+        print_blocks(&mut blocks, &plaintext).unwrap();
+        let output = frame_output(&hex::encode(&frame), &key);
+        assert_eq!(output.as_bytes(), blocks);
+        assert!(output.contains("Init"), "{}", output);
+
+        let output = frame_output(&hex::encode(&frame), &hash_key("sky+2.1.3.0+1634050057"));
+        assert!(output.starts_with("MAC check failed"), "{}", output);
+
+        let mut long = frame.clone();
+        long.push(0);
+        let output = frame_output(&hex::encode(&long), &key);
+        assert!(output.starts_with(&format!("length header says {} bytes follow it, but {} do", frame.len() - HEADER_LEN, long.len() - HEADER_LEN)), "{}", output);
+
+        assert!(frame_output("12 21 zz", &key).starts_with("not hex"));
+        assert!(frame_output("1221", &key).starts_with("frame is only 2 bytes"));
+    }
 }
@@ -0,0 +1,431 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+
+use etherparse::{NetSlice, SlicedPacket, TransportSlice};
+use pcap_parser::pcapng::Block;
+use pcap_parser::{create_reader, Linktype, PcapBlockOwned, PcapError};
+use session::frame::HEADER_LEN;
+use session::{decode_length_header, decrypt, FrameDecoder, Key, KeyFormat, SecretKey};
+
+use crate::reassembly::Reassembler;
+use crate::{print_blocks, print_handshake};
+
+// client public key in front of the handshake frame
+const CLIENT_KEY_LEN: usize = 32;
+
+/// one tcp segment out of a capture
+#[derive(Debug, Clone)]
+pub struct Segment<'a> {
+    /// seconds since the epoch
+    pub ts: f64,
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+    pub seq: u32,
+    pub syn: bool,
+    pub payload: &'a [u8],
+}
+
+// the tcp segment in a packet, if it is one
+fn segment(linktype: Linktype, ts: f64, data: &[u8]) -> Option<Segment<'_>> {
+    let packet = match linktype {
+        Linktype::ETHERNET => SlicedPacket::from_ethernet(data),
+        Linktype::LINUX_SLL => SlicedPacket::from_linux_sll(data),
+        Linktype::RAW | Linktype::IPV4 | Linktype::IPV6 => SlicedPacket::from_ip(data),
+        // loopback captures have a 4 byte address family in front
+        Linktype::NULL | Linktype::LOOP => SlicedPacket::from_ip(data.get(4..)?),
+        _ => return None,
+    }
+    .ok()?;
+    let (src, dst): (IpAddr, IpAddr) = match packet.net? {
+        NetSlice::Ipv4(ip) => (ip.header().source_addr().into(), ip.header().destination_addr().into()),
+        NetSlice::Ipv6(ip) => (ip.header().source_addr().into(), ip.header().destination_addr().into()),
+        NetSlice::Arp(_) => return None,
+    };
+    match packet.transport? {
+        TransportSlice::Tcp(tcp) => Some(Segment {
+            ts,
+            src: SocketAddr::new(src, tcp.source_port()),
+            dst: SocketAddr::new(dst, tcp.destination_port()),
+            seq: tcp.sequence_number(),
+            syn: tcp.syn(),
+            payload: tcp.payload(),
+        }),
+        _ => None,
+    }
+}
+
+/// calls `f` with every tcp segment in a pcap or pcapng file, in capture order, stopping if it fails
+pub fn read_segments(path: &Path, mut f: impl FnMut(Segment) -> io::Result<()>) -> Result<(), String> {
+    let file = File::open(path).map_err(|e| format!("can't open {}: {}", path.display(), e))?;
+    let mut reader = create_reader(1 << 20, file).map_err(|e| format!("not a capture: {:?}", e))?;
+    let mut linktype = Linktype::ETHERNET;
+    let mut nanos = false;
+    // pcapng: linktype, units per second and offset of each interface in the current section
+    let mut interfaces = Vec::new();
+
+    loop {
+        let (offset, block) = match reader.next() {
+            Ok(next) => next,
+            Err(PcapError::Eof) => return Ok(()),
+            Err(PcapError::Incomplete(_)) => {
+                reader.refill().map_err(|e| format!("read failed: {:?}", e))?;
+                continue;
+            }
+            Err(e) => return Err(format!("bad capture: {:?}", e)),
+        };
+        match block {
+            PcapBlockOwned::LegacyHeader(header) => {
+                linktype = header.network;
+                nanos = header.is_nanosecond_precision();
+            }
+            PcapBlockOwned::Legacy(packet) => {
+                let fraction = if nanos { 1e9 } else { 1e6 };
+                let ts = packet.ts_sec as f64 + packet.ts_usec as f64 / fraction;
+                if let Some(segment) = segment(linktype, ts, packet.data) {
+                    f(segment).map_err(|e| format!("write failed: {}", e))?;
+                }
+            }
+            PcapBlockOwned::NG(Block::SectionHeader(_)) => interfaces.clear(),
+            PcapBlockOwned::NG(Block::InterfaceDescription(idb)) => {
+                interfaces.push((idb.linktype, idb.ts_resolution().unwrap_or(1_000_000), idb.ts_offset()));
+            }
+            PcapBlockOwned::NG(Block::EnhancedPacket(epb)) => {
+                if let Some(&(linktype, resolution, ts_offset)) = interfaces.get(epb.if_id as usize) {
+                    let ts = epb.decode_ts_f64(ts_offset as u64, resolution);
+                    let data = &epb.data[..(epb.caplen as usize).min(epb.data.len())];
+                    if let Some(segment) = segment(linktype, ts, data) {
+                        f(segment).map_err(|e| format!("write failed: {}", e))?;
+                    }
+                }
+            }
+            // no timestamp, and always on the first interface
+            PcapBlockOwned::NG(Block::SimplePacket(spb)) => {
+                if let Some(&(linktype, _, _)) = interfaces.first() {
+                    if let Some(segment) = segment(linktype, 0.0, spb.data) {
+                        f(segment).map_err(|e| format!("write failed: {}", e))?;
+                    }
+                }
+            }
+            PcapBlockOwned::NG(_) => {}
+        }
+        reader.consume(offset);
+    }
+}
+
+/// which way a message went
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Direction {
+    ToServer,
+    ToClient,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ToServer => f.write_str(">"),
+            Self::ToClient => f.write_str("<"),
+        }
+    }
+}
+
+/// how frames get decrypted; a fixed key wins over one derived from the handshake
+#[derive(Debug, Clone, Default)]
+pub struct Keys {
+    pub key: Option<Key>,
+    /// opens handshakes, so the key can be derived from the fingerprint in them
+    pub secret_key: Option<SecretKey>,
+    pub key_format: KeyFormat,
+}
+
+// one connection to the server port
+#[derive(Debug, Default)]
+struct Flow {
+    to_server: Reassembler,
+    to_client: Reassembler,
+    // the client's stream up to the end of the handshake
+    handshake: Option<Vec<u8>>,
+    from_client: FrameDecoder,
+    from_server: FrameDecoder,
+    key: Option<Key>,
+}
+
+impl Flow {
+    fn new(key: Option<Key>) -> Self {
+        Flow { handshake: Some(Vec::new()), key, ..Flow::default() }
+    }
+
+    // complains about anything the capture never filled in
+    fn finish(self, client: SocketAddr, server: SocketAddr) {
+        let missing = self.to_server.pending() + self.to_client.pending();
+        if missing > 0 {
+            eprintln!("{} - {}: {} bytes stuck behind segments missing from the capture", client, server, missing);
+        }
+    }
+}
+
+// the length of the whole handshake, once there's enough of it to tell
+fn handshake_len(buffer: &[u8]) -> Result<Option<usize>, session::Error> {
+    let Some(header) = buffer.get(CLIENT_KEY_LEN..CLIENT_KEY_LEN + HEADER_LEN) else {
+        return Ok(None);
+    };
+    let len = decode_length_header(header.try_into().unwrap())? as usize;
+    Ok(Some(CLIENT_KEY_LEN + HEADER_LEN + len))
+}
+
+/// writes out every message to and from `port` in the capture
+pub fn dump(path: &Path, port: u16, keys: &Keys, out: &mut impl Write) -> Result<(), String> {
+    let mut flows: HashMap<(SocketAddr, SocketAddr), Flow> = HashMap::new();
+    let result = read_segments(path, |segment| {
+        let (client, server, direction) = match (segment.src.port(), segment.dst.port()) {
+            (_, dst) if dst == port => (segment.src, segment.dst, Direction::ToServer),
+            (src, _) if src == port => (segment.dst, segment.src, Direction::ToClient),
+            _ => return Ok(()),
+        };
+        // the client port got reused; the new connection has its own isn and its own handshake
+        if segment.syn && direction == Direction::ToServer {
+            if let Some(old) = flows.insert((client, server), Flow::new(keys.key.clone())) {
+                old.finish(client, server);
+            }
+        }
+        let flow = flows.entry((client, server)).or_insert_with(|| Flow::new(keys.key.clone()));
+        let header = format!("{:.6} {} {} {}", segment.ts, client, direction, server);
+
+        let mut data = match direction {
+            Direction::ToServer => flow.to_server.push(segment.seq, segment.syn, segment.payload),
+            Direction::ToClient => flow.to_client.push(segment.seq, segment.syn, segment.payload),
+        };
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        if direction == Direction::ToServer {
+            if let Some(mut handshake) = flow.handshake.take() {
+                handshake.append(&mut data);
+                match handshake_len(&handshake) {
+                    Ok(Some(len)) if handshake.len() >= len => {
+                        data = handshake.split_off(len);
+                        writeln!(out, "{} handshake", header)?;
+                        match &keys.secret_key {
+                            Some(secret_key) => {
+                                let fingerprint = print_handshake(out, &handshake, secret_key)?;
+                                if let (None, Some(fingerprint)) = (&flow.key, fingerprint) {
+                                    match fingerprint.session_key(&keys.key_format) {
+                                        Ok(key) => flow.key = Some(key),
+                                        Err(e) => writeln!(out, "can't derive the session key: {}", e)?,
+                                    }
+                                }
+                            }
+                            None => writeln!(out, "{}", hex::encode(&handshake))?,
+                        }
+                        writeln!(out, "\n")?;
+                    }
+                    Ok(_) => {
+                        flow.handshake = Some(handshake);
+                        return Ok(());
+                    }
+                    // not our protocol, or we came in mid-connection; try it as frames
+                    Err(e) => {
+                        writeln!(out, "{} no handshake: {}\n\n", header, e)?;
+                        data = handshake;
+                    }
+                }
+            }
+        }
+
+        let decoder = match direction {
+            Direction::ToServer => &mut flow.from_client,
+            Direction::ToClient => &mut flow.from_server,
+        };
+        decoder.push(&data);
+        loop {
+            let frame = match decoder.next_frame() {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break,
+                Err(e) => {
+                    writeln!(out, "{} {}, dropping {} bytes\n\n", header, e, decoder.pending())?;
+                    *decoder = FrameDecoder::new();
+                    break;
+                }
+            };
+            writeln!(out, "{}", header)?;
+            match &flow.key {
+                Some(key) => match decrypt(key, &frame) {
+                    Ok(plaintext) => print_blocks(out, &plaintext)?,
+                    Err(e) => writeln!(out, "{}: {}", e, hex::encode(&frame))?,
+                },
+                None => writeln!(out, "no key: {}", hex::encode(&frame))?,
+            }
+            writeln!(out, "\n")?;
+        }
+        Ok(())
+    });
+    for ((client, server), flow) in flows {
+        flow.finish(client, server);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use etherparse::PacketBuilder;
+    use protocol::message;
+    use session::{encrypt, make_handshake, Fingerprint, Randomness, SeededRandom};
+    use std::path::PathBuf;
+
+    const CLIENT: [u8; 4] = [10, 0, 0, 1];
+    const SERVER: [u8; 4] = [10, 0, 0, 2];
+
+    // an ipv4 packet carrying a tcp segment
+    fn tcp(to_server: bool, client_port: u16, seq: u32, syn: bool, payload: &[u8]) -> Vec<u8> {
+        let builder = match to_server {
+            true => PacketBuilder::ipv4(CLIENT, SERVER, 64).tcp(client_port, 6666, seq, 1024),
+            false => PacketBuilder::ipv4(SERVER, CLIENT, 64).tcp(6666, client_port, seq, 1024),
+        };
+        let builder = if syn { builder.syn() } else { builder };
+        let mut packet = Vec::new();
+        builder.write(&mut packet, payload).unwrap();
+        packet
+    }
+
+    // the ip packet with the link layer header `linktype` wants in front
+    fn link(linktype: Linktype, packet: &[u8]) -> Vec<u8> {
+        let header = match linktype {
+            Linktype::ETHERNET => [&[2; 6][..], &[1; 6], &[0x08, 0x00]].concat(),
+            // outgoing, ethernet, a 6 byte address padded to 8, ipv4
+            Linktype::LINUX_SLL => [&[0, 4, 0, 1, 0, 6][..], &[1; 8], &[0x08, 0x00]].concat(),
+            Linktype::NULL => 2u32.to_le_bytes().to_vec(),
+            _ => Vec::new(),
+        };
+        [header, packet.to_vec()].concat()
+    }
+
+    // a pcapng block, padded out to 4 bytes
+    fn block(kind: u32, body: &[u8]) -> Vec<u8> {
+        let padded = body.len().next_multiple_of(4);
+        let len = (12 + padded) as u32;
+        [&kind.to_le_bytes()[..], &len.to_le_bytes(), body, &vec![0; padded - body.len()], &len.to_le_bytes()].concat()
+    }
+
+    // writes `packets` out as a capture, packet `i` at `i.5` seconds
+    fn capture(name: &str, linktype: Linktype, ng: bool, packets: &[Vec<u8>]) -> PathBuf {
+        let mut file = Vec::new();
+        if ng {
+            file.extend(block(0x0A0D0D0A, &[&0x1A2B3C4Du32.to_le_bytes()[..], &1u16.to_le_bytes(), &0u16.to_le_bytes(), &(-1i64).to_le_bytes()].concat()));
+            file.extend(block(1, &[&(linktype.0 as u16).to_le_bytes()[..], &[0, 0], &65535u32.to_le_bytes()].concat()));
+        } else {
+            file.extend(0xa1b2c3d4u32.to_le_bytes());
+            file.extend(2u16.to_le_bytes());
+            file.extend(4u16.to_le_bytes());
+            file.extend([0; 8]);
+            file.extend(65535u32.to_le_bytes());
+            file.extend((linktype.0 as u32).to_le_bytes());
+        }
+        for (i, packet) in packets.iter().enumerate() {
+            let data = link(linktype, packet);
+            let len = (data.len() as u32).to_le_bytes();
+            if ng {
+                let ts = i as u64 * 1_000_000 + 500_000;
+                let header = [0u32, (ts >> 32) as u32, ts as u32].map(u32::to_le_bytes).concat();
+                file.extend(block(6, &[&header[..], &len, &len, &data].concat()));
+            } else {
+                file.extend((i as u32).to_le_bytes());
+                file.extend(500_000u32.to_le_bytes());
+                file.extend(len);
+                file.extend(len);
+                file.extend(data);
+            }
+        }
+        let path = std::env::temp_dir().join(format!("parse-cli-{}-{}.pcap", name, std::process::id()));
+        std::fs::write(&path, file).unwrap();
+        path
+    }
+
+    // two connections from the same client port: a handshake split across segments with the
+    // first request riding along behind it, and a listing coming back
+    fn session(secret: &SecretKey, fingerprint: &Fingerprint) -> Vec<Vec<u8>> {
+        let handshake = make_handshake(fingerprint, &secret.public_key());
+        let key = fingerprint.session_key(&KeyFormat::default()).unwrap();
+        let request = encrypt(&key, message! { cmd: ListDir, dir: "/" }.as_bytes().to_vec()).unwrap();
+        let response = encrypt(&key, message! { cmd: ListDir, folder: "a.txt", code: 0 }.as_bytes().to_vec()).unwrap();
+        let rest = [&handshake[20..], &request[..]].concat();
+        [1000, 900_000]
+            .into_iter()
+            .flat_map(|isn| {
+                [
+                    tcp(true, 40000, isn, true, b""),
+                    tcp(false, 40000, 5000, true, b""),
+                    tcp(true, 40000, isn + 1, false, &handshake[..20]),
+                    tcp(true, 40000, isn + 21, false, &rest),
+                    tcp(false, 40000, 5001, false, &response),
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_read_segments() {
+        let (_, secret) = SeededRandom::new([3; 32]).keypair();
+        let packets = session(&secret, &Fingerprint::default());
+        for linktype in [Linktype::ETHERNET, Linktype::LINUX_SLL, Linktype::RAW, Linktype::NULL] {
+            for ng in [false, true] {
+                let path = capture(&format!("segments-{}-{}", linktype.0, ng), linktype, ng, &packets);
+                let mut segments = Vec::new();
+                read_segments(&path, |x| {
+                    segments.push((x.ts, x.src.to_string(), x.dst.to_string(), x.seq, x.syn, x.payload.len()));
+                    Ok(())
+                })
+                .unwrap();
+                assert_eq!(segments.len(), packets.len(), "{:?} ng {}", linktype, ng);
+                assert_eq!(segments[0], (0.5, "10.0.0.1:40000".to_string(), "10.0.0.2:6666".to_string(), 1000, true, 0));
+                assert_eq!(segments[1], (1.5, "10.0.0.2:6666".to_string(), "10.0.0.1:40000".to_string(), 5000, true, 0));
+                assert_eq!((segments[2].3, segments[2].5), (1001, 20));
+            }
+        }
+        // a linktype we can't take apart has no segments in it
+        let path = capture("segments-unknown", Linktype(147), false, &packets);
+        read_segments(&path, |_| panic!("no segments")).unwrap();
+        assert!(read_segments(Path::new("/nonexistent.pcap"), |_| Ok(())).is_err());
+    }
+
+    #[test]
+    fn test_dump() {
+        let (_, secret) = SeededRandom::new([3; 32]).keypair();
+        let fingerprint = Fingerprint { username: "bob".to_string(), ..Fingerprint::default() };
+        let packets = session(&secret, &fingerprint);
+        let keys = Keys { secret_key: Some(secret), ..Keys::default() };
+        for ng in [false, true] {
+            let path = capture(&format!("dump-{}", ng), Linktype::ETHERNET, ng, &packets);
+            let mut out = Vec::new();
+            dump(&path, 6666, &keys, &mut out).unwrap();
+            let out = String::from_utf8(out).unwrap();
+
+            let headers = out.lines().filter(|x| x.contains(":6666")).collect::<Vec<_>>();
+            assert_eq!(
+                headers,
+                [
+                    "3.500000 10.0.0.1:40000 > 10.0.0.2:6666 handshake",
+                    "3.500000 10.0.0.1:40000 > 10.0.0.2:6666",
+                    "4.500000 10.0.0.1:40000 < 10.0.0.2:6666",
+                    // same port, new isn
+                    "8.500000 10.0.0.1:40000 > 10.0.0.2:6666 handshake",
+                    "8.500000 10.0.0.1:40000 > 10.0.0.2:6666",
+                    "9.500000 10.0.0.1:40000 < 10.0.0.2:6666",
+                ]
+            );
+            assert_eq!(out.matches("username: bob").count(), 2);
+            assert_eq!(out.matches("Param(DirName(\"/\"))").count(), 2);
+            assert_eq!(out.matches("Param(FolderContents(\"a.txt\"))").count(), 2);
+        }
+
+        // no way to get the key: the handshake and frames come out as hex
+        let path = capture("dump-nokey", Linktype::RAW, false, &packets);
+        let mut out = Vec::new();
+        dump(&path, 6666, &Keys::default(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap().matches("no key: ").count(), 4);
+    }
+}
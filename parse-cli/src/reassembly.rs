@@ -0,0 +1,93 @@
+/// puts one direction of a tcp connection back in order; retransmits and overlaps are dropped
+#[derive(Debug, Clone, Default)]
+pub struct Reassembler {
+    // sequence number of the next byte we want, once we've seen the first segment
+    next: Option<u32>,
+    // segments from past a gap, waiting for it to be filled
+    pending: Vec<(u32, Vec<u8>)>,
+}
+
+// how far `seq` is past `next`, negative if it's behind; sequence numbers wrap
+fn offset(seq: u32, next: u32) -> i64 {
+    seq.wrapping_sub(next) as i32 as i64
+}
+
+impl Reassembler {
+    /// takes a segment and returns whatever's now contiguous; a capture starting mid-connection
+    /// picks up from its first segment
+    pub fn push(&mut self, seq: u32, syn: bool, data: &[u8]) -> Vec<u8> {
+        // the syn takes up a sequence number but carries nothing
+        let seq = if syn { seq.wrapping_add(1) } else { seq };
+        let next = *self.next.get_or_insert(seq);
+        if offset(seq, next) > 0 {
+            self.pending.push((seq, data.to_vec()));
+            return Vec::new();
+        }
+
+        let mut output = Vec::new();
+        self.take(seq, data, &mut output);
+        // anything pending that's caught up now
+        while let Some(i) = self.pending.iter().position(|(seq, _)| offset(*seq, self.next.unwrap()) <= 0) {
+            let (seq, data) = self.pending.swap_remove(i);
+            self.take(seq, &data, &mut output);
+        }
+        output
+    }
+
+    // appends the part of a segment starting at or before `next` that we haven't had yet
+    fn take(&mut self, seq: u32, data: &[u8], output: &mut Vec<u8>) {
+        let next = self.next.unwrap();
+        let seen = (-offset(seq, next)) as usize;
+        if seen < data.len() {
+            output.extend_from_slice(&data[seen..]);
+            self.next = Some(next.wrapping_add((data.len() - seen) as u32));
+        }
+    }
+
+    /// bytes sitting past a gap
+    pub fn pending(&self) -> usize {
+        self.pending.iter().map(|(_, data)| data.len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_order() {
+        let mut stream = Reassembler::default();
+        assert_eq!(stream.push(100, true, b""), b"");
+        assert_eq!(stream.push(101, false, b"abc"), b"abc");
+        assert_eq!(stream.push(104, false, b"de"), b"de");
+    }
+
+    #[test]
+    fn test_out_of_order() {
+        let mut stream = Reassembler::default();
+        stream.push(0, true, b"");
+        assert_eq!(stream.push(4, false, b"def"), b"");
+        assert_eq!(stream.push(7, false, b"g"), b"");
+        assert_eq!(stream.pending(), 4);
+        assert_eq!(stream.push(1, false, b"abc"), b"abcdefg");
+        assert_eq!(stream.pending(), 0);
+    }
+
+    #[test]
+    fn test_retransmit() {
+        let mut stream = Reassembler::default();
+        assert_eq!(stream.push(10, false, b"abc"), b"abc");
+        assert_eq!(stream.push(10, false, b"abc"), b"");
+        // overlaps what we have and adds to it
+        assert_eq!(stream.push(11, false, b"bcde"), b"de");
+        assert_eq!(stream.push(15, false, b"f"), b"f");
+    }
+
+    #[test]
+    fn test_wrap() {
+        let mut stream = Reassembler::default();
+        stream.push(u32::MAX - 1, true, b"");
+        assert_eq!(stream.push(1, false, b"cd"), b"");
+        assert_eq!(stream.push(u32::MAX, false, b"ab"), b"abcd");
+    }
+}
@@ -0,0 +1,7 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = protocol::parse(data);
+    let _ = protocol::parse_with(data, protocol::SizeWidth::Wide);
+});
@@ -0,0 +1,19 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use protocol::{parse_with, SizeWidth};
+
+// parse isn't byte-for-byte reversible (lossy utf8, missing nul terminators), but whatever
+// it produces has to survive serialize -> parse unchanged, at the width it was read with
+fuzz_target!(|data: &[u8]| {
+    for width in [SizeWidth::Narrow, SizeWidth::Wide] {
+        if let Ok((_, parsed)) = parse_with(data, width) {
+            // lossy utf8 can grow a string past what its size field holds
+            let Ok(bytes) = parsed.clone().try_to_proto_bytes() else {
+                continue;
+            };
+            let (rest, reparsed) = parse_with(&bytes, width).expect("serialized message doesn't parse");
+            assert!(rest.is_empty());
+            assert_eq!(parsed, reparsed);
+        }
+    }
+});
@@ -0,0 +1,125 @@
+use std::marker::PhantomData;
+
+use crate::protocol::*;
+
+/// builder state before a command has been set
+#[derive(Debug, Clone)]
+pub struct NeedsCmd;
+
+/// builder state once a command has been set; only now can the message be finished
+#[derive(Debug, Clone)]
+pub struct HasCmd;
+
+/// builds a well formed [`Message`]: Start magic, the command, params, End magic
+///
+/// the magic is added for you and leaving out the command doesn't compile:
+///
+/// ```compile_fail
+/// use protocol::MessageBuilder;
+/// let message = MessageBuilder::new().uuid([0; 16]).finish();
+/// ```
+///
+/// ```
+/// use protocol::{Command, MessageBuilder};
+/// let message = MessageBuilder::new().cmd(Command::Init).uuid([0; 16]).finish();
+/// ```
+///
+/// use [`Message::append`] when you need something the server isn't expecting
+#[derive(Debug, Clone)]
+pub struct MessageBuilder<S> {
+    message: Message,
+    state: PhantomData<S>,
+}
+
+impl MessageBuilder<NeedsCmd> {
+    pub fn new() -> Self {
+        let mut message = Message::new();
+        message.append(Magic::Start);
+        MessageBuilder { message, state: PhantomData }
+    }
+
+    pub fn cmd(mut self, cmd: Command) -> MessageBuilder<HasCmd> {
+        self.message.append(Param::Cmd(cmd));
+        MessageBuilder { message: self.message, state: PhantomData }
+    }
+}
+
+impl Default for MessageBuilder<NeedsCmd> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MessageBuilder<HasCmd> {
+    fn param(mut self, param: Param) -> Self {
+        self.message.append(param);
+        self
+    }
+
+    pub fn uuid(self, uuid: [u8; 16]) -> Self {
+        self.param(Param::Uuid(uuid))
+    }
+
+    pub fn dir(self, dir: impl Into<String>) -> Self {
+        self.param(Param::DirName(dir.into()))
+    }
+
+    pub fn folder(self, contents: impl Into<String>) -> Self {
+        self.param(Param::FolderContents(contents.into()))
+    }
+
+    pub fn file(self, file: impl Into<String>) -> Self {
+        self.param(Param::FileName(file.into()))
+    }
+
+    pub fn contents(self, contents: impl Into<Vec<u8>>) -> Self {
+        self.param(Param::Contents(contents.into()))
+    }
+
+    pub fn more(self, more: impl Into<String>) -> Self {
+        self.param(Param::More(more.into()))
+    }
+
+    pub fn code(self, code: u32) -> Self {
+        self.param(Param::Code(code))
+    }
+
+    pub fn finish(mut self) -> Message {
+        self.message.append(Magic::End);
+        self.message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{blocks, parse};
+    use assert_hex::assert_eq_hex;
+    use hex_literal::hex;
+
+    #[test]
+    fn test_builder() {
+        let msg = MessageBuilder::new()
+            .cmd(Command::Init)
+            .uuid(hex!("c2cd31ed27134010a0dedfc817a341b7"))
+            .finish();
+        assert_eq_hex!(
+            &msg.to_proto_bytes(),
+            &hex!("19b0a81d4d00000200024d080010c2cd31ed27134010a0dedfc817a341b7eda9f5ce")
+        );
+    }
+
+    #[test]
+    fn test_builder_order() {
+        let msg = MessageBuilder::new()
+            .cmd(Command::Upload)
+            .dir("/tmp")
+            .file("a.txt")
+            .contents(b"hello".to_vec())
+            .finish();
+        assert_eq!(
+            parse(msg.as_bytes()).unwrap().1,
+            blocks![start, cmd: Upload, dir: "/tmp", file: "a.txt", contents: b"hello", end]
+        );
+    }
+}
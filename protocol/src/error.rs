@@ -1,4 +1,3 @@
-use thiserror::Error;
 use std::fmt::Display;
 use std::fmt;
 
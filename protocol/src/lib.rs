@@ -1,6 +1,15 @@
 mod parser;
+#[allow(dead_code)]
 mod error;
 mod protocol;
+mod parsed;
+mod builder;
+mod path;
+pub mod mutate;
+pub mod macros;
 
-pub use parser::parse;
+pub use parser::{parse, parse_with};
+pub use parsed::ParsedMessage;
+pub use builder::{HasCmd, MessageBuilder, NeedsCmd};
+pub use path::{PathStyle, RemotePath};
 pub use crate::protocol::*;
\ No newline at end of file
@@ -0,0 +1,153 @@
+/// parses a 32 character hex uuid; meant for `const` contexts, where a bad uuid fails the build
+pub const fn uuid(hex: &str) -> [u8; 16] {
+    const fn nibble(c: u8) -> u8 {
+        match c {
+            b'0'..=b'9' => c - b'0',
+            b'a'..=b'f' => c - b'a' + 10,
+            b'A'..=b'F' => c - b'A' + 10,
+            _ => panic!("uuid has a non hex character"),
+        }
+    }
+
+    let hex = hex.as_bytes();
+    if hex.len() != 32 {
+        panic!("uuid must be 32 hex characters");
+    }
+    let mut uuid = [0u8; 16];
+    let mut i = 0;
+    while i < 16 {
+        uuid[i] = (nibble(hex[i * 2]) << 4) | nibble(hex[i * 2 + 1]);
+        i += 1;
+    }
+    uuid
+}
+
+/// builds a [`Message`](crate::Message) through [`MessageBuilder`](crate::MessageBuilder), so the
+/// command is required and the magic is added for you
+///
+/// keys are the builder methods (`uuid`, `dir`, `folder`, `file`, `contents`, `more`, `code`) and
+/// show up on the wire in the order they're written. a string literal uuid is checked at compile time
+///
+/// ```
+/// use protocol::message;
+/// let msg = message! { cmd: ReadFile, uuid: "000102030405060708090a0b0c0d0f10", dir: "/tmp", file: "a.txt" };
+/// ```
+///
+/// ```compile_fail
+/// use protocol::message;
+/// let msg = message! { cmd: Init, uuid: "not a uuid" };
+/// ```
+#[macro_export]
+macro_rules! message {
+    (cmd: $cmd:ident $(, $($rest:tt)*)?) => {
+        $crate::message!(@params $crate::MessageBuilder::new().cmd($crate::Command::$cmd); $($($rest)*)?)
+    };
+    (@params $builder:expr; ) => {
+        $builder.finish()
+    };
+    (@params $builder:expr; uuid: $uuid:literal $(, $($rest:tt)*)?) => {
+        $crate::message!(@params $builder.uuid({ const UUID: [u8; 16] = $crate::macros::uuid($uuid); UUID }); $($($rest)*)?)
+    };
+    (@params $builder:expr; $key:ident: $value:expr $(, $($rest:tt)*)?) => {
+        $crate::message!(@params $builder.$key($value); $($($rest)*)?)
+    };
+}
+
+/// builds a raw `Vec<Block>` in exactly the order given; nothing is added or checked, so it's
+/// handy for fuzz seeds and malformed test vectors
+///
+/// `start` and `end` are the magic, everything else uses the same keys as [`message!`]
+///
+/// ```
+/// use protocol::blocks;
+/// let seed = blocks![start, uuid: "000102030405060708090a0b0c0d0f10", cmd: Init, end, end];
+/// ```
+#[macro_export]
+macro_rules! blocks {
+    (@acc []; ) => {
+        ::std::vec::Vec::<$crate::Block>::new()
+    };
+    (@acc [$($out:expr),*]; ) => {
+        ::std::vec![$($out),*]
+    };
+    (@acc [$($out:expr),*]; start $(, $($rest:tt)*)?) => {
+        $crate::blocks!(@acc [$($out,)* $crate::Block::Magic($crate::Magic::Start)]; $($($rest)*)?)
+    };
+    (@acc [$($out:expr),*]; end $(, $($rest:tt)*)?) => {
+        $crate::blocks!(@acc [$($out,)* $crate::Block::Magic($crate::Magic::End)]; $($($rest)*)?)
+    };
+    (@acc [$($out:expr),*]; cmd: $cmd:ident $(, $($rest:tt)*)?) => {
+        $crate::blocks!(@acc [$($out,)* $crate::Block::Param($crate::Param::Cmd($crate::Command::$cmd))]; $($($rest)*)?)
+    };
+    (@acc [$($out:expr),*]; uuid: $uuid:literal $(, $($rest:tt)*)?) => {
+        $crate::blocks!(@acc [$($out,)* $crate::Block::Param($crate::Param::Uuid({ const UUID: [u8; 16] = $crate::macros::uuid($uuid); UUID }))]; $($($rest)*)?)
+    };
+    (@acc [$($out:expr),*]; $key:ident: $value:expr $(, $($rest:tt)*)?) => {
+        $crate::blocks!(@acc [$($out,)* $crate::Block::Param($crate::blocks!(@param $key $value))]; $($($rest)*)?)
+    };
+    (@param uuid $value:expr) => { $crate::Param::Uuid($value) };
+    (@param dir $value:expr) => { $crate::Param::DirName(($value).into()) };
+    (@param folder $value:expr) => { $crate::Param::FolderContents(($value).into()) };
+    (@param file $value:expr) => { $crate::Param::FileName(($value).into()) };
+    (@param contents $value:expr) => { $crate::Param::Contents(($value).into()) };
+    (@param more $value:expr) => { $crate::Param::More(($value).into()) };
+    (@param code $value:expr) => { $crate::Param::Code($value) };
+    ($($rest:tt)*) => {
+        $crate::blocks!(@acc []; $($rest)*)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::uuid;
+    use crate::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn test_uuid() {
+        assert_eq!(uuid("c2cd31ed27134010a0dedfc817a341b7"), hex!("c2cd31ed27134010a0dedfc817a341b7"));
+        assert_eq!(uuid("C2CD31ED27134010A0DEDFC817A341B7"), hex!("c2cd31ed27134010a0dedfc817a341b7"));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_uuid_invalid() {
+        uuid("c2cd31ed27134010a0dedfc817a341bz");
+    }
+
+    #[test]
+    fn test_message() {
+        assert_eq!(
+            message! { cmd: Init, uuid: "c2cd31ed27134010a0dedfc817a341b7" },
+            Message::make_init(hex!("c2cd31ed27134010a0dedfc817a341b7"))
+        );
+        let uuid = hex!("c2cd31ed27134010a0dedfc817a341b7");
+        assert_eq!(
+            message! { cmd: ReadFile, uuid: uuid, dir: "/tmp", file: "a.txt", },
+            Message::make_read_file(uuid, &RemotePath::posix("/tmp"), "a.txt")
+        );
+        assert_eq!(
+            parse(message! { cmd: Upload, dir: "/tmp", file: "a.txt", contents: b"hi", code: 0 }.as_bytes())
+                .unwrap()
+                .1,
+            blocks![start, cmd: Upload, dir: "/tmp", file: "a.txt", contents: b"hi", code: 0, end]
+        );
+    }
+
+    #[test]
+    fn test_blocks() {
+        assert_eq!(blocks![], Vec::<Block>::new());
+        assert_eq!(
+            blocks![end, more: "x", start],
+            vec![
+                Block::Magic(Magic::End),
+                Block::Param(Param::More("x".to_string())),
+                Block::Magic(Magic::Start),
+            ]
+        );
+        assert_eq!(
+            blocks![start, cmd: Init, uuid: "c2cd31ed27134010a0dedfc817a341b7", end].to_proto_bytes(),
+            hex!("19b0a81d4d00000200024d080010c2cd31ed27134010a0dedfc817a341b7eda9f5ce")
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use std::fmt;
+
+use crate::parse;
+use crate::protocol::*;
+
+/// length used for oversized contents; unreasonable for a single param but still fits in one frame
+pub const OVERSIZED_LEN: usize = 0xF000;
+
+/// command values that don't map to any known [`Command`]
+pub const UNKNOWN_COMMANDS: [u16; 4] = [0x0000, 0x0001, 0x0008, 0xFFFF];
+
+/// a single systematic change applied to an otherwise valid message
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Mutation {
+    /// the declared size of the param at `block` is off by `delta`
+    LengthOffByOne { block: usize, delta: i8 },
+    /// the string at `block` is cut in half without touching its declared size
+    TruncatedString { block: usize },
+    /// the tags of the params at `first` and `second` are swapped
+    SwappedTags { first: usize, second: usize },
+    /// the value at `block` is replaced with [`OVERSIZED_LEN`] bytes
+    OversizedContents { block: usize },
+    /// the given magic is dropped
+    MissingMagic(Magic),
+    /// the command is replaced with a value outside of [`Command`]
+    UnknownCommand(u16),
+}
+
+impl fmt::Display for Mutation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LengthOffByOne { block, delta } => write!(f, "length {:+} on block {}", delta, block),
+            Self::TruncatedString { block } => write!(f, "truncated string on block {}", block),
+            Self::SwappedTags { first, second } => write!(f, "swapped tags on blocks {} and {}", first, second),
+            Self::OversizedContents { block } => write!(f, "oversized contents on block {}", block),
+            Self::MissingMagic(magic) => write!(f, "missing {:?} magic", magic),
+            Self::UnknownCommand(cmd) => write!(f, "unknown command {:#06x}", cmd),
+        }
+    }
+}
+
+/// a mutated message; `bytes` is usually not parseable anymore, so it's kept raw
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Mutant {
+    pub mutation: Mutation,
+    pub bytes: Vec<u8>,
+}
+
+// a block and where it sits in the serialized message
+struct Span {
+    block: Block,
+    start: usize,
+    end: usize,
+}
+
+fn spans(message: &[u8]) -> Vec<Span> {
+    let blocks = match parse(message) {
+        Ok((_, parsed)) => parsed.into_blocks(),
+        Err(_) => return Vec::new(),
+    };
+    let mut offset = 0;
+    blocks
+        .into_iter()
+        .map(|block| {
+            let len = block.clone().to_proto_bytes().len();
+            let span = Span { block, start: offset, end: offset + len };
+            offset += len;
+            span
+        })
+        .collect()
+}
+
+fn splice(message: &[u8], start: usize, end: usize, with: &[u8]) -> Vec<u8> {
+    let mut output = message[..start].to_vec();
+    output.extend_from_slice(with);
+    output.extend_from_slice(&message[end..]);
+    output
+}
+
+/// every systematic mutation of `message`; empty if the message doesn't parse
+pub fn mutate(message: &Message) -> Vec<Mutant> {
+    let data = message.as_bytes();
+    let spans = spans(data);
+    let mut output = Vec::new();
+
+    for (i, span) in spans.iter().enumerate() {
+        let param = match &span.block {
+            Block::Param(param) => param,
+            Block::Magic(magic) => {
+                output.push(Mutant {
+                    mutation: Mutation::MissingMagic(magic.clone()),
+                    bytes: splice(data, span.start, span.end, &[]),
+                });
+                continue;
+            }
+            Block::Command(_) => continue,
+        };
+
+        // tag and size are always the first four bytes of a param
+        let size = u16::from_be_bytes([data[span.start + 2], data[span.start + 3]]);
+        for delta in [1i8, -1] {
+            let size = size.wrapping_add(delta as u16);
+            output.push(Mutant {
+                mutation: Mutation::LengthOffByOne { block: i, delta },
+                bytes: splice(data, span.start + 2, span.start + 4, &size.to_be_bytes()),
+            });
+        }
+
+        match param {
+            Param::DirName(_) | Param::FolderContents(_) | Param::FileName(_) | Param::More(_) => {
+                let value_len = span.end - span.start - 4;
+                output.push(Mutant {
+                    mutation: Mutation::TruncatedString { block: i },
+                    bytes: splice(data, span.start + 4 + value_len / 2, span.end, &[]),
+                });
+            }
+            Param::Cmd(_) => {
+                for cmd in UNKNOWN_COMMANDS {
+                    output.push(Mutant {
+                        mutation: Mutation::UnknownCommand(cmd),
+                        bytes: splice(data, span.start + 4, span.end, &cmd.to_be_bytes()),
+                    });
+                }
+            }
+            _ => {}
+        }
+
+        if let Param::DirName(_) | Param::FolderContents(_) | Param::FileName(_) | Param::More(_) | Param::Contents(_) = param {
+            let mut oversized = data[span.start..span.start + 2].to_vec();
+            oversized.extend((OVERSIZED_LEN as u16).to_be_bytes());
+            oversized.resize(4 + OVERSIZED_LEN, b'A');
+            if param.as_str().is_some() {
+                oversized[3 + OVERSIZED_LEN] = 0;
+            }
+            output.push(Mutant {
+                mutation: Mutation::OversizedContents { block: i },
+                bytes: splice(data, span.start, span.end, &oversized),
+            });
+        }
+
+        if let Some(Span { block: Block::Param(next), start, .. }) = spans.get(i + 1) {
+            if next.kind() != param.kind() {
+                let mut bytes = data.to_vec();
+                bytes[span.start..span.start + 2].copy_from_slice(&data[*start..start + 2]);
+                bytes[*start..start + 2].copy_from_slice(&data[span.start..span.start + 2]);
+                output.push(Mutant {
+                    mutation: Mutation::SwappedTags { first: i, second: i + 1 },
+                    bytes,
+                });
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RemotePath;
+    use hex_literal::hex;
+
+    #[test]
+    fn test_mutate_init() {
+        let message = Message::make_init(hex!("c2cd31ed27134010a0dedfc817a341b7"));
+        let mutants = mutate(&message);
+        let find = |mutation: Mutation| mutants.iter().find(|x| x.mutation == mutation).unwrap().bytes.clone();
+
+        assert_eq!(
+            find(Mutation::MissingMagic(Magic::Start)),
+            hex!("4d00000200024d080010c2cd31ed27134010a0dedfc817a341b7eda9f5ce")
+        );
+        assert_eq!(
+            find(Mutation::MissingMagic(Magic::End)),
+            hex!("19b0a81d4d00000200024d080010c2cd31ed27134010a0dedfc817a341b7")
+        );
+        assert_eq!(
+            find(Mutation::LengthOffByOne { block: 2, delta: -1 }),
+            hex!("19b0a81d4d00000200024d08000fc2cd31ed27134010a0dedfc817a341b7eda9f5ce")
+        );
+        assert_eq!(
+            find(Mutation::UnknownCommand(0xFFFF)),
+            hex!("19b0a81d4d000002ffff4d080010c2cd31ed27134010a0dedfc817a341b7eda9f5ce")
+        );
+        assert_eq!(
+            find(Mutation::SwappedTags { first: 1, second: 2 }),
+            hex!("19b0a81d4d08000200024d000010c2cd31ed27134010a0dedfc817a341b7eda9f5ce")
+        );
+        // init has no strings or contents
+        assert!(!mutants.iter().any(|x| matches!(x.mutation, Mutation::TruncatedString { .. } | Mutation::OversizedContents { .. })));
+    }
+
+    #[test]
+    fn test_mutate_strings() {
+        let message = Message::make_list_dir(hex!("c2cd31ed27134010a0dedfc817a341b7"), &RemotePath::posix("/tmp"));
+        let mutants = mutate(&message);
+        let find = |mutation: Mutation| mutants.iter().find(|x| x.mutation == mutation).unwrap().bytes.clone();
+
+        assert_eq!(
+            find(Mutation::TruncatedString { block: 3 }),
+            hex!("19b0a81d4d00000200044d080010c2cd31ed27134010a0dedfc817a341b7 4d1400052f74 eda9f5ce")
+        );
+        let oversized = find(Mutation::OversizedContents { block: 3 });
+        assert_eq!(oversized.len(), message.as_bytes().len() - 5 + OVERSIZED_LEN);
+        assert!(parse(&oversized).is_ok());
+    }
+
+    #[test]
+    fn test_mutate_invalid() {
+        assert!(mutate(Message::new().append(Param::Code(0))).is_empty());
+    }
+}
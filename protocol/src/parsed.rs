@@ -0,0 +1,152 @@
+use crate::protocol::*;
+
+/// a parsed message; keeps the blocks in wire order, and the width of the Contents size, so it can
+/// be re-serialized as-is
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct ParsedMessage {
+    blocks: Vec<Block>,
+    contents_width: SizeWidth,
+}
+
+impl ParsedMessage {
+    pub fn new(blocks: Vec<Block>) -> Self {
+        Self::new_with(blocks, SizeWidth::Narrow)
+    }
+
+    pub fn new_with(blocks: Vec<Block>, contents_width: SizeWidth) -> Self {
+        ParsedMessage { blocks, contents_width }
+    }
+
+    pub fn contents_width(&self) -> SizeWidth {
+        self.contents_width
+    }
+
+    /// the message as it was read, or an error if a value has grown too long for its size field
+    pub fn try_to_proto_bytes(self) -> Result<Vec<u8>, TooLong> {
+        let width = self.contents_width;
+        self.blocks.into_iter().map(|x| x.try_to_proto_bytes_with(width)).collect::<Result<Vec<_>, _>>().map(|x| x.concat())
+    }
+
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    pub fn into_blocks(self) -> Vec<Block> {
+        self.blocks
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Block> {
+        self.blocks.iter()
+    }
+
+    /// every param in wire order, skipping magic
+    pub fn params(&self) -> impl Iterator<Item = &Param> {
+        self.blocks.iter().filter_map(|x| match x {
+            Block::Param(param) => Some(param),
+            _ => None,
+        })
+    }
+
+    /// the first param of the given kind
+    pub fn get(&self, kind: ParamKind) -> Option<&Param> {
+        self.get_all(kind).next()
+    }
+
+    /// every param of the given kind, in wire order
+    pub fn get_all(&self, kind: ParamKind) -> impl Iterator<Item = &Param> {
+        self.params().filter(move |x| x.kind() == kind)
+    }
+
+    pub fn command(&self) -> Option<&Command> {
+        match self.get(ParamKind::Cmd) {
+            Some(Param::Cmd(cmd)) => Some(cmd),
+            _ => None,
+        }
+    }
+
+    pub fn uuid(&self) -> Option<&[u8; 16]> {
+        match self.get(ParamKind::Uuid) {
+            Some(Param::Uuid(uuid)) => Some(uuid),
+            _ => None,
+        }
+    }
+
+    pub fn code(&self) -> Option<u32> {
+        match self.get(ParamKind::Code) {
+            Some(Param::Code(code)) => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+impl From<Vec<Block>> for ParsedMessage {
+    fn from(blocks: Vec<Block>) -> Self {
+        ParsedMessage::new(blocks)
+    }
+}
+
+impl PartialEq<Vec<Block>> for ParsedMessage {
+    fn eq(&self, other: &Vec<Block>) -> bool {
+        &self.blocks == other
+    }
+}
+
+impl IntoIterator for ParsedMessage {
+    type Item = Block;
+    type IntoIter = std::vec::IntoIter<Block>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.blocks.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a ParsedMessage {
+    type Item = &'a Block;
+    type IntoIter = std::slice::Iter<'a, Block>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.blocks.iter()
+    }
+}
+
+impl Protocol for ParsedMessage {
+    /// panics if a value has grown too long for its size field; see [`ParsedMessage::try_to_proto_bytes`]
+    fn to_proto_bytes(self) -> Vec<u8> {
+        self.try_to_proto_bytes().unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use crate::RemotePath;
+    use hex_literal::hex;
+
+    #[test]
+    fn test_accessors() {
+        let message = Message::make_read_file(hex!("c2cd31ed27134010a0dedfc817a341b7"), &RemotePath::posix("/tmp"), "a.txt").to_proto_bytes();
+        let (_, parsed) = parse(&message).unwrap();
+        assert_eq!(parsed.command(), Some(&Command::ReadFile));
+        assert_eq!(parsed.uuid(), Some(&hex!("c2cd31ed27134010a0dedfc817a341b7")));
+        assert_eq!(parsed.code(), None);
+        assert_eq!(parsed.get(ParamKind::DirName).and_then(Param::as_str), Some("/tmp"));
+        assert_eq!(parsed.get(ParamKind::FileName).and_then(Param::as_str), Some("a.txt"));
+        assert_eq!(parsed.to_proto_bytes(), message);
+    }
+
+    #[test]
+    fn test_get_all() {
+        let (_, parsed) = parse(&hex!(
+            "19B0A81D4D28000400000000 4D1800026100 4D1800026200 4D1800026300 EDA9F5CE"
+        ))
+        .unwrap();
+        assert_eq!(parsed.code(), Some(0));
+        assert_eq!(
+            parsed.get_all(ParamKind::FolderContents).filter_map(Param::as_str).collect::<Vec<_>>(),
+            vec!["a", "b", "c"]
+        );
+        assert_eq!(parsed.iter().count(), 6);
+        assert_eq!(parsed.iter().next(), Some(&Block::Magic(Magic::Start)));
+    }
+}
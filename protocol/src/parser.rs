@@ -1,72 +1,80 @@
 use nom::{bytes::complete::tag, IResult};
 
+use crate::parsed::ParsedMessage;
 use crate::protocol::*;
-use byteorder::{BigEndian, ReadBytesExt};
 use hex_literal::hex;
 use nom::branch::alt;
 use nom::bytes::complete::take;
+use nom::combinator::map;
+use nom::error::ErrorKind;
 use nom::multi::many1;
-use num_traits::{FromPrimitive, ToPrimitive};
+use nom::number::complete::{be_u16, be_u32};
 
 fn match_end_magic(input: &[u8]) -> IResult<&[u8], Block> {
     let (input, _) = tag(Magic::End.to_proto_bytes().as_slice())(input)?;
     Ok((input, Block::Magic(Magic::End)))
 }
 
+// fixed-size params still carry a size; it has to agree with what the param actually is
+fn fixed_size(input: &[u8], expected: u16) -> IResult<&[u8], &[u8]> {
+    let (rest, size) = be_u16(input)?;
+    if size != expected {
+        return Err(nom::Err::Failure(nom::error::Error::new(input, ErrorKind::LengthValue)));
+    }
+    take(size)(rest)
+}
+
 fn match_param_command(input: &[u8]) -> IResult<&[u8], Block> {
     let (input, _) = tag(hex!("4D00"))(input)?;
-    let (input, _) = take(2usize)(input)?; // ALWAYS 2?
-    let (input, cmd) = take(2usize)(input)?;
-    let cmd = Command::from_u16(((cmd[0] as u16) << 8u16) | cmd[1] as u16);
-    Ok((input, Block::Param(Param::Cmd(cmd))))
+    let (rest, cmd) = fixed_size(input, 2)?;
+    let cmd = Command::from_u16(((cmd[0] as u16) << 8u16) | cmd[1] as u16)
+        .ok_or_else(|| nom::Err::Failure(nom::error::Error::new(input, ErrorKind::Verify)))?;
+    Ok((rest, Block::Param(Param::Cmd(cmd))))
 }
 
-fn match_param_string(input: &[u8], param: [u8; 2]) -> IResult<&[u8], Block> {
+fn match_param_string(input: &[u8], param: [u8; 2], make: fn(String) -> Param) -> IResult<&[u8], Block> {
     let (input, _) = tag(param)(input)?;
-    let (input, size) = take(2usize)(input)?; // ALWAYS 2?
-    let size = (((size[0] as u16) << 8u16) | size[1] as u16) as usize;
-    let (input, str) = take(size)(input)?;
-    let mut str = String::from_utf8_lossy(&str[..str.len() - 1]).to_string();
-    Ok((
-        input,
-        Block::Param(match param {
-            hex!("4D14") => Param::DirName(str),
-            hex!("4D18") => Param::FolderContents(str),
-            hex!("4D1C") => Param::FileName(str),
-            hex!("4D24") => Param::More(str),
-            _ => panic!("um dont do that lol"),
-        }),
-    ))
+    let (rest, size) = be_u16(input)?;
+    let (rest, str) = take(size)(rest)?;
+    // strings are nul terminated, so there's always at least one byte
+    let str = match str.split_last() {
+        Some((_, str)) => String::from_utf8_lossy(str).to_string(),
+        None => return Err(nom::Err::Failure(nom::error::Error::new(input, ErrorKind::LengthValue))),
+    };
+    Ok((rest, Block::Param(make(str))))
 }
 
 fn match_param_dirname(input: &[u8]) -> IResult<&[u8], Block> {
-    match_param_string(input, hex!("4D14"))
+    match_param_string(input, hex!("4D14"), Param::DirName)
 }
 
 fn match_param_folder_contents(input: &[u8]) -> IResult<&[u8], Block> {
-    match_param_string(input, hex!("4D18"))
+    match_param_string(input, hex!("4D18"), Param::FolderContents)
 }
 
 fn match_param_filename(input: &[u8]) -> IResult<&[u8], Block> {
-    match_param_string(input, hex!("4D1C"))
+    match_param_string(input, hex!("4D1C"), Param::FileName)
 }
 
-fn match_param_contents(input: &[u8]) -> IResult<&[u8], Block> {
-    let (input, _) = tag(hex!("4D20"))(input)?;
-    let (input, size) = take(2usize)(input)?; // ALWAYS 2?
-    let size = (((size[0] as u16) << 8u16) | size[1] as u16) as usize;
-    let (input, contents) = take(size)(input)?;
-    Ok((input, Block::Param(Param::Contents(contents.to_vec()))))
+fn match_param_contents(width: SizeWidth) -> impl Fn(&[u8]) -> IResult<&[u8], Block> {
+    move |input| {
+        let (input, _) = tag(hex!("4D20"))(input)?;
+        let (input, size) = match width {
+            SizeWidth::Narrow => map(be_u16, u32::from)(input)?,
+            SizeWidth::Wide => be_u32(input)?,
+        };
+        let (input, contents) = take(size)(input)?;
+        Ok((input, Block::Param(Param::Contents(contents.to_vec()))))
+    }
 }
 
 fn match_param_more(input: &[u8]) -> IResult<&[u8], Block> {
-    match_param_string(input, hex!("4D24"))
+    match_param_string(input, hex!("4D24"), Param::More)
 }
 
 fn match_param_uuid(input: &[u8]) -> IResult<&[u8], Block> {
     let (input, _) = tag(hex!("4D08"))(input)?;
-    let (input, _) = take(2usize)(input)?; // ALWAYS 2?
-    let (input, uuid) = take(16usize)(input)?;
+    let (input, uuid) = fixed_size(input, 16)?;
     let mut uuid_sized = [0u8; 16];
     uuid_sized.copy_from_slice(uuid);
     Ok((input, Block::Param(Param::Uuid(uuid_sized))))
@@ -74,15 +82,17 @@ fn match_param_uuid(input: &[u8]) -> IResult<&[u8], Block> {
 
 fn match_param_code(input: &[u8]) -> IResult<&[u8], Block> {
     let (input, _) = tag(hex!("4D28"))(input)?;
-    let (input, _) = take(2usize)(input)?; // ALWAYS 2?
-    let (input, mut response) = take(4usize)(input)?;
-    Ok((
-        input,
-        Block::Param(Param::Code(response.read_u32::<BigEndian>().unwrap())),
-    ))
+    let (input, response) = fixed_size(input, 4)?;
+    let (_, code) = be_u32(response)?;
+    Ok((input, Block::Param(Param::Code(code))))
+}
+
+pub fn parse(input: &[u8]) -> IResult<&[u8], ParsedMessage> {
+    parse_with(input, SizeWidth::Narrow)
 }
 
-pub fn parse(input: &[u8]) -> IResult<&[u8], Vec<Block>> {
+/// like [`parse`], but with the given size field width for Contents
+pub fn parse_with(input: &[u8], contents_width: SizeWidth) -> IResult<&[u8], ParsedMessage> {
     let mut output = Vec::new();
     let (input, _) = tag(Magic::Start.to_proto_bytes().as_slice())(input)?;
     output.push(Block::Magic(Magic::Start));
@@ -94,185 +104,176 @@ pub fn parse(input: &[u8]) -> IResult<&[u8], Vec<Block>> {
         match_param_filename,
         match_param_uuid,
         match_param_code,
-        match_param_contents,
+        match_param_contents(contents_width),
         match_param_more,
         match_end_magic,
     )))(input)?;
     output.extend(params);
-    Ok((input, output))
+    Ok((input, ParsedMessage::new_with(output, contents_width)))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::blocks;
+    use proptest::prelude::*;
 
-    #[test]
-    fn test_match_commands() {
-        assert_eq!(
-            match_param_command(&hex!("4D0000020002")).unwrap().1,
-            Block::Param(Param::Cmd(Command::Init))
-        );
-        assert_eq!(
-            match_param_command(&hex!("4D0000020003")).unwrap().1,
-            Block::Param(Param::Cmd(Command::GetSessionFolder))
-        );
-        assert_eq!(
-            match_param_command(&hex!("4D0000020004")).unwrap().1,
-            Block::Param(Param::Cmd(Command::ListDir))
-        );
-        assert_eq!(
-            match_param_command(&hex!("4D0000020005")).unwrap().1,
-            Block::Param(Param::Cmd(Command::ReadFile))
-        );
-        assert_eq!(
-            match_param_command(&hex!("4D0000020006")).unwrap().1,
-            Block::Param(Param::Cmd(Command::Upload))
-        );
-        assert_eq!(
-            match_param_command(&hex!("4D0000020007")).unwrap().1,
-            Block::Param(Param::Cmd(Command::Fin))
-        );
+    fn arb_command() -> impl Strategy<Value = Command> {
+        prop_oneof![
+            Just(Command::Init),
+            Just(Command::GetSessionFolder),
+            Just(Command::ListDir),
+            Just(Command::ReadFile),
+            Just(Command::Upload),
+            Just(Command::Fin),
+        ]
     }
 
-    #[test]
-    fn test_match_param() {
-        assert_eq!(
-            match_param_uuid(&hex!("4D080010FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF"))
-                .unwrap()
-                .1,
-            Block::Param(Param::Uuid(hex!("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF")))
-        );
-
-        assert_eq!(
-            match_param_string(&hex!("4D1400084141414141414100"), hex!("4D14"))
-                .unwrap()
-                .1,
-            Block::Param(Param::DirName("AAAAAAA".to_string()))
-        );
-
-        assert_eq!(
-            match_param_dirname(&hex!("4D1400084141414141414100"))
-                .unwrap()
-                .1,
-            Block::Param(Param::DirName("AAAAAAA".to_string()))
-        );
+    fn arb_param() -> impl Strategy<Value = Param> {
+        prop_oneof![
+            arb_command().prop_map(Param::Cmd),
+            any::<[u8; 16]>().prop_map(Param::Uuid),
+            ".{0,64}".prop_map(Param::DirName),
+            ".{0,64}".prop_map(Param::FolderContents),
+            ".{0,64}".prop_map(Param::FileName),
+            prop::collection::vec(any::<u8>(), 0..256).prop_map(Param::Contents),
+            ".{0,64}".prop_map(Param::More),
+            any::<u32>().prop_map(Param::Code),
+        ]
+    }
 
-        assert_eq!(
-            match_param_folder_contents(&hex!("4D1800084141414141414100"))
-                .unwrap()
-                .1,
-            Block::Param(Param::FolderContents("AAAAAAA".to_string()))
-        );
+    fn arb_blocks() -> impl Strategy<Value = Vec<Block>> {
+        prop::collection::vec(arb_param(), 0..16).prop_map(|params| {
+            let mut blocks = vec![Block::Magic(Magic::Start)];
+            blocks.extend(params.into_iter().map(Block::Param));
+            blocks.push(Block::Magic(Magic::End));
+            blocks
+        })
+    }
 
-        assert_eq!(
-            match_param_filename(&hex!("4D1C00084141414141414100"))
-                .unwrap()
-                .1,
-            Block::Param(Param::FileName("AAAAAAA".to_string()))
-        );
+    proptest! {
+        #[test]
+        fn prop_roundtrip(blocks in arb_blocks()) {
+            let bytes = blocks.clone().to_proto_bytes();
+            let (rest, parsed) = parse(&bytes).unwrap();
+            prop_assert!(rest.is_empty());
+            prop_assert_eq!(parsed, blocks);
+        }
 
-        assert_eq!(
-            match_param_contents(&hex!("4D20000741414141414141"))
-                .unwrap()
-                .1,
-            Block::Param(Param::Contents("AAAAAAA".bytes().collect()))
-        );
+        #[test]
+        fn prop_parse_never_panics(bytes in prop::collection::vec(any::<u8>(), 0..128)) {
+            let mut input = Magic::Start.to_proto_bytes();
+            input.extend(bytes);
+            let _ = parse(&input);
+        }
+    }
 
-        assert_eq!(
-            match_param_more(&hex!("4D2400084141414141414100"))
-                .unwrap()
-                .1,
-            Block::Param(Param::More("AAAAAAA".to_string()))
-        );
+    #[test]
+    fn test_regressions() {
+        // zero sized string used to underflow
+        assert!(parse(&hex!("19B0A81D4D140000EDA9F5CE")).is_err());
+        // unknown command used to panic
+        assert!(parse(&hex!("19B0A81D4D0000020009EDA9F5CE")).is_err());
+        assert!(parse(&hex!("19B0A81D4D000002FFFFEDA9F5CE")).is_err());
+    }
 
-        assert_eq!(
-            match_param_code(&hex!("4D28000400000009")).unwrap().1,
-            Block::Param(Param::Code(9))
-        );
+    #[test]
+    fn test_match_commands() {
+        for (input, expected) in [
+            (hex!("4D0000020002"), blocks![cmd: Init]),
+            (hex!("4D0000020003"), blocks![cmd: GetSessionFolder]),
+            (hex!("4D0000020004"), blocks![cmd: ListDir]),
+            (hex!("4D0000020005"), blocks![cmd: ReadFile]),
+            (hex!("4D0000020006"), blocks![cmd: Upload]),
+            (hex!("4D0000020007"), blocks![cmd: Fin]),
+        ] {
+            assert_eq!(vec![match_param_command(&input).unwrap().1], expected);
+        }
     }
 
     #[test]
-    fn test_parse() {
+    fn test_match_param() {
+        let one = |result: IResult<&[u8], Block>| vec![result.unwrap().1];
         assert_eq!(
-            parse(&hex!("19B0A81DEDA9F5CE")).unwrap().1,
-            vec![Block::Magic(Magic::Start), Block::Magic(Magic::End),]
+            one(match_param_uuid(&hex!("4D080010FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF"))),
+            blocks![uuid: "ffffffffffffffffffffffffffffffff"]
         );
-
         assert_eq!(
-            parse(&hex!(
-                "19B0A81D4D080010FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEDA9F5CE"
-            ))
-            .unwrap()
-            .1,
-            vec![
-                Block::Magic(Magic::Start),
-                Block::Param(Param::Uuid(hex!("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF"))),
-                Block::Magic(Magic::End),
-            ]
+            one(match_param_string(&hex!("4D1400084141414141414100"), hex!("4D14"), Param::DirName)),
+            blocks![dir: "AAAAAAA"]
         );
-
+        assert_eq!(one(match_param_dirname(&hex!("4D1400084141414141414100"))), blocks![dir: "AAAAAAA"]);
+        assert_eq!(one(match_param_folder_contents(&hex!("4D1800084141414141414100"))), blocks![folder: "AAAAAAA"]);
+        assert_eq!(one(match_param_filename(&hex!("4D1C00084141414141414100"))), blocks![file: "AAAAAAA"]);
         assert_eq!(
-            parse(&hex!("19B0A81D4D1400084141414141414100EDA9F5CE"))
-                .unwrap()
-                .1,
-            vec![
-                Block::Magic(Magic::Start),
-                Block::Param(Param::DirName("AAAAAAA".to_string())),
-                Block::Magic(Magic::End),
-            ]
+            one(match_param_contents(SizeWidth::Narrow)(&hex!("4D20000741414141414141"))),
+            blocks![contents: b"AAAAAAA"]
         );
+        assert_eq!(one(match_param_more(&hex!("4D2400084141414141414100"))), blocks![more: "AAAAAAA"]);
+        assert_eq!(one(match_param_code(&hex!("4D28000400000009"))), blocks![code: 9]);
+    }
 
+    #[test]
+    fn test_match_wide_contents() {
         assert_eq!(
-            parse(&hex!("19B0A81D4D1800084141414141414100EDA9F5CE"))
+            match_param_contents(SizeWidth::Wide)(&hex!("4D200000000741414141414141"))
                 .unwrap()
                 .1,
-            vec![
-                Block::Magic(Magic::Start),
-                Block::Param(Param::FolderContents("AAAAAAA".to_string())),
-                Block::Magic(Magic::End),
-            ]
+            Block::Param(Param::Contents("AAAAAAA".bytes().collect()))
         );
 
-        assert_eq!(
-            parse(&hex!("19B0A81D4D1C00084141414141414100EDA9F5CE"))
-                .unwrap()
-                .1,
-            vec![
-                Block::Magic(Magic::Start),
-                Block::Param(Param::FileName("AAAAAAA".to_string())),
-                Block::Magic(Magic::End),
-            ]
-        );
+        let contents = vec![0x41u8; 0x12345];
+        let bytes = Param::Contents(contents.clone()).to_proto_bytes_with(SizeWidth::Wide);
+        assert_eq!(&bytes[..6], &hex!("4D2000012345"));
+        let message = [
+            Magic::Start.to_proto_bytes(),
+            bytes,
+            Magic::End.to_proto_bytes(),
+        ]
+        .concat();
+        let (rest, parsed) = parse_with(&message, SizeWidth::Wide).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(parsed.get(ParamKind::Contents).and_then(Param::as_bytes), Some(contents.as_slice()));
+        // and goes back out just as wide
+        assert_eq!(parsed.contents_width(), SizeWidth::Wide);
+        assert_eq!(parsed.to_proto_bytes(), message);
+    }
 
+    #[test]
+    fn test_fixed_size_mismatch() {
         assert_eq!(
-            parse(&hex!("19B0A81D4D20000741414141414141EDA9F5CE"))
-                .unwrap()
-                .1,
-            vec![
-                Block::Magic(Magic::Start),
-                Block::Param(Param::Contents("AAAAAAA".bytes().collect())),
-                Block::Magic(Magic::End),
-            ]
+            match_param_uuid(&hex!("4D080011FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF")),
+            Err(nom::Err::Failure(nom::error::Error::new(
+                &hex!("0011FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF")[..],
+                ErrorKind::LengthValue
+            )))
         );
+        assert!(match_param_command(&hex!("4D00000400000002")).is_err());
+        assert!(match_param_code(&hex!("4D2800020009")).is_err());
 
-        assert_eq!(
-            parse(&hex!("19B0A81D4D2400084141414141414100EDA9F5CE"))
-                .unwrap()
-                .1,
-            vec![
-                Block::Magic(Magic::Start),
-                Block::Param(Param::More("AAAAAAA".to_string())),
-                Block::Magic(Magic::End),
-            ]
-        );
+        // a disagreeing size aborts the whole message instead of leaving it half parsed
+        assert!(matches!(
+            parse(&hex!("19B0A81D4D28000200094D080010FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEDA9F5CE")),
+            Err(nom::Err::Failure(_))
+        ));
+    }
 
-        assert_eq!(
-            parse(&hex!("19B0A81D4D28000400000009EDA9F5CE")).unwrap().1,
-            vec![
-                Block::Magic(Magic::Start),
-                Block::Param(Param::Code(9)),
-                Block::Magic(Magic::End),
-            ]
-        );
+    #[test]
+    fn test_parse() {
+        for (input, expected) in [
+            (&hex!("19B0A81DEDA9F5CE")[..], blocks![start, end]),
+            (
+                &hex!("19B0A81D4D080010FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEDA9F5CE"),
+                blocks![start, uuid: "ffffffffffffffffffffffffffffffff", end],
+            ),
+            (&hex!("19B0A81D4D1400084141414141414100EDA9F5CE"), blocks![start, dir: "AAAAAAA", end]),
+            (&hex!("19B0A81D4D1800084141414141414100EDA9F5CE"), blocks![start, folder: "AAAAAAA", end]),
+            (&hex!("19B0A81D4D1C00084141414141414100EDA9F5CE"), blocks![start, file: "AAAAAAA", end]),
+            (&hex!("19B0A81D4D20000741414141414141EDA9F5CE"), blocks![start, contents: b"AAAAAAA", end]),
+            (&hex!("19B0A81D4D2400084141414141414100EDA9F5CE"), blocks![start, more: "AAAAAAA", end]),
+            (&hex!("19B0A81D4D28000400000009EDA9F5CE"), blocks![start, code: 9, end]),
+        ] {
+            assert_eq!(parse(input).unwrap().1, expected);
+        }
     }
 }
@@ -0,0 +1,227 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// how paths look on the target, independent of the machine we're running on
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum PathStyle {
+    /// `/` separated
+    #[default]
+    Posix,
+    /// `\` separated with an optional drive; `/` is accepted when parsing
+    Windows,
+}
+
+impl PathStyle {
+    pub fn separator(self) -> char {
+        match self {
+            Self::Posix => '/',
+            Self::Windows => '\\',
+        }
+    }
+
+    fn is_separator(self, c: char) -> bool {
+        match self {
+            Self::Posix => c == '/',
+            Self::Windows => c == '\\' || c == '/',
+        }
+    }
+}
+
+impl FromStr for PathStyle {
+    type Err = String;
+
+    /// `posix` or `windows`
+    fn from_str(style: &str) -> Result<Self, String> {
+        match style.to_ascii_lowercase().as_str() {
+            "posix" => Ok(Self::Posix),
+            "windows" => Ok(Self::Windows),
+            _ => Err(format!("{:?} isn't a path style, try posix or windows", style)),
+        }
+    }
+}
+
+/// a normalized path on the target; `.` is dropped and `..` is resolved, never climbing above the root
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct RemotePath {
+    style: PathStyle,
+    // drive, e.g. `C:`; windows only
+    prefix: Option<String>,
+    absolute: bool,
+    components: Vec<String>,
+}
+
+impl RemotePath {
+    pub fn new(path: &str, style: PathStyle) -> Self {
+        let mut rest = path;
+        let mut prefix = None;
+        if style == PathStyle::Windows {
+            let bytes = path.as_bytes();
+            if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+                prefix = Some(path[..2].to_ascii_uppercase());
+                rest = &path[2..];
+            }
+        }
+        let mut output = RemotePath {
+            style,
+            prefix,
+            absolute: rest.starts_with(|c| style.is_separator(c)),
+            components: Vec::new(),
+        };
+        output.extend(rest);
+        output
+    }
+
+    pub fn posix(path: &str) -> Self {
+        Self::new(path, PathStyle::Posix)
+    }
+
+    pub fn windows(path: &str) -> Self {
+        Self::new(path, PathStyle::Windows)
+    }
+
+    pub fn root(style: PathStyle) -> Self {
+        Self::new(&style.separator().to_string(), style)
+    }
+
+    pub fn style(&self) -> PathStyle {
+        self.style
+    }
+
+    pub fn is_absolute(&self) -> bool {
+        self.absolute
+    }
+
+    pub fn components(&self) -> &[String] {
+        &self.components
+    }
+
+    pub fn file_name(&self) -> Option<&str> {
+        self.components.last().filter(|x| *x != "..").map(String::as_str)
+    }
+
+    // appends already split components, resolving `.` and `..` as it goes
+    fn extend(&mut self, path: &str) {
+        for component in path.split(|c| self.style.is_separator(c)) {
+            match component {
+                "" | "." => {}
+                ".." => match self.components.last() {
+                    Some(last) if last != ".." => {
+                        self.components.pop();
+                    }
+                    _ if self.absolute => {}
+                    _ => self.components.push("..".to_string()),
+                },
+                component => self.components.push(component.to_string()),
+            }
+        }
+    }
+
+    /// `path` relative to this one; an absolute `path` replaces it (keeping the drive if it has none)
+    pub fn join(&self, path: &str) -> Self {
+        let other = Self::new(path, self.style);
+        if other.prefix.is_some() {
+            return other;
+        }
+        if other.absolute {
+            return RemotePath { prefix: self.prefix.clone(), ..other };
+        }
+        let mut output = self.clone();
+        output.extend(path);
+        output
+    }
+
+    pub fn push(&mut self, path: &str) {
+        *self = self.join(path);
+    }
+
+    /// the containing directory, or `None` at the root
+    pub fn parent(&self) -> Option<Self> {
+        let mut output = self.clone();
+        if output.pop() {
+            Some(output)
+        } else {
+            None
+        }
+    }
+
+    pub fn pop(&mut self) -> bool {
+        match self.components.last() {
+            Some(last) if last != ".." => {
+                self.components.pop();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for RemotePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separator = self.style.separator().to_string();
+        if let Some(prefix) = &self.prefix {
+            f.write_str(prefix)?;
+        }
+        if self.absolute {
+            f.write_str(&separator)?;
+        } else if self.prefix.is_none() && self.components.is_empty() {
+            return f.write_str(".");
+        }
+        f.write_str(&self.components.join(&separator))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_posix() {
+        assert_eq!(RemotePath::posix("/").to_string(), "/");
+        assert_eq!(RemotePath::posix("/tmp//a/./b/").to_string(), "/tmp/a/b");
+        assert_eq!(RemotePath::posix("/tmp/../../etc").to_string(), "/etc");
+        assert_eq!(RemotePath::posix("a/../../b").to_string(), "../b");
+        assert_eq!(RemotePath::posix("").to_string(), ".");
+        assert_eq!(RemotePath::posix("a\\b").components(), &["a\\b".to_string()]);
+    }
+
+    #[test]
+    fn test_windows() {
+        assert_eq!(RemotePath::windows("c:\\Users\\sky").to_string(), "C:\\Users\\sky");
+        assert_eq!(RemotePath::windows("C:/Users/../Windows").to_string(), "C:\\Windows");
+        assert_eq!(RemotePath::windows("\\tmp").to_string(), "\\tmp");
+        assert_eq!(RemotePath::windows("D:").to_string(), "D:");
+        assert_eq!(RemotePath::root(PathStyle::Windows).to_string(), "\\");
+        assert_eq!("Windows".parse(), Ok(PathStyle::Windows));
+        assert!("dos".parse::<PathStyle>().is_err());
+    }
+
+    #[test]
+    fn test_join() {
+        let cwd = RemotePath::posix("/home/sky");
+        assert_eq!(cwd.join("docs").to_string(), "/home/sky/docs");
+        assert_eq!(cwd.join("..").to_string(), "/home");
+        assert_eq!(cwd.join("../../../..").to_string(), "/");
+        assert_eq!(cwd.join("/etc/./passwd").to_string(), "/etc/passwd");
+
+        let cwd = RemotePath::windows("C:\\Users");
+        assert_eq!(cwd.join("sky\\Desktop").to_string(), "C:\\Users\\sky\\Desktop");
+        assert_eq!(cwd.join("\\Windows").to_string(), "C:\\Windows");
+        assert_eq!(cwd.join("D:\\data").to_string(), "D:\\data");
+    }
+
+    #[test]
+    fn test_parent() {
+        let path = RemotePath::posix("/home/sky");
+        assert_eq!(path.file_name(), Some("sky"));
+        assert_eq!(path.parent(), Some(RemotePath::posix("/home")));
+        assert_eq!(RemotePath::posix("/home").parent(), Some(RemotePath::posix("/")));
+        assert_eq!(RemotePath::posix("/").parent(), None);
+        assert_eq!(RemotePath::posix("..").parent(), None);
+
+        let mut path = RemotePath::windows("C:\\a\\b");
+        assert!(path.pop());
+        assert_eq!(path.to_string(), "C:\\a");
+        path.push("c");
+        assert_eq!(path.to_string(), "C:\\a\\c");
+    }
+}
@@ -1,7 +1,12 @@
-use std::path::Path;
-use enum_primitive_derive::Primitive;
+use crate::builder::MessageBuilder;
+use crate::path::RemotePath;
 use hex_literal::hex;
-use serde::{Deserialize, Serialize};
+use serde::{Serialize, Serializer};
+
+// bytes go out as hex in json and the like, not as an array of numbers
+fn as_hex<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&hex::encode(bytes))
+}
 
 
 pub trait Protocol {
@@ -9,7 +14,7 @@ pub trait Protocol {
 }
 
 #[repr(u32)]
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
 pub enum Magic {
     Start = 0x19B0A81D,
     End = 0xEDA9F5CE,
@@ -22,74 +27,135 @@ impl Protocol for Magic {
 }
 
 #[repr(u16)]
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
 pub enum Param {
     Cmd(Command) = 0x4D00,
-    Uuid([u8; 16]) = 0x4D08,
+    Uuid(#[serde(serialize_with = "as_hex")] [u8; 16]) = 0x4D08,
     DirName(String) = 0x4D14,
     FolderContents(String) = 0x4D18,
     FileName(String) = 0x4D1C,
-    Contents(Vec<u8>) = 0x4D20,
+    Contents(#[serde(serialize_with = "as_hex")] Vec<u8>) = 0x4D20,
     More(String) = 0x4D24,
     Code(u32) = 0x4D28,
 }
 
+/// width of the size field in front of a Contents param
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum SizeWidth {
+    /// the usual 2 byte size
+    #[default]
+    Narrow,
+    /// a 4 byte size, for contents that don't fit in a u16
+    Wide,
+}
+
 impl Protocol for Param {
     fn to_proto_bytes(self) -> Vec<u8> {
-        let mut data = Vec::new();
+        self.to_proto_bytes_with(SizeWidth::Narrow)
+    }
+}
+
+/// a param value too long for its size field
+#[derive(Debug, Clone, Copy, Eq, PartialEq, thiserror::Error)]
+#[error("{kind:?} is {len} bytes, too long for its size field")]
+pub struct TooLong {
+    pub kind: ParamKind,
+    pub len: usize,
+}
+
+impl Param {
+    /// panics if the value doesn't fit its size field; see [`Param::try_to_proto_bytes_with`]
+    pub fn to_proto_bytes_with(self, contents_width: SizeWidth) -> Vec<u8> {
+        self.try_to_proto_bytes_with(contents_width).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_to_proto_bytes_with(self, contents_width: SizeWidth) -> Result<Vec<u8>, TooLong> {
+        let kind = self.kind();
+        let too_long = |len| TooLong { kind, len };
+        let mut data = kind.tag().to_be_bytes().to_vec();
         match self {
             Self::Cmd(cmd) => {
-                data.extend(0x4D00_u16.to_be_bytes().into_iter());
-                data.extend(hex!("0002").into_iter());
+                data.extend(hex!("0002"));
                 data.append(&mut cmd.to_proto_bytes());
             }
             Self::Uuid(uuid) => {
-                data.extend(0x4D08_u16.to_be_bytes().into_iter());
-                data.extend(hex!("0010").into_iter());
-                data.extend(uuid.into_iter());
-            }
-            Self::DirName(s) => {
-                data.extend(0x4D14_u16.to_be_bytes().into_iter());
-                data.extend(((s.len() + 1) as u16).to_be_bytes());
-                data.extend(s.bytes());
-                data.push(0);
-            }
-            Self::FolderContents(s) => {
-                data.extend(0x4D18_u16.to_be_bytes().into_iter());
-                data.extend(((s.len() + 1) as u16).to_be_bytes());
-                data.extend(s.bytes());
-                data.push(0);
+                data.extend(hex!("0010"));
+                data.extend(uuid);
             }
-            Self::FileName(s) => {
-                data.extend(0x4D1c_u16.to_be_bytes().into_iter());
-                data.extend(((s.len() + 1) as u16).to_be_bytes());
+            // the size counts the nul
+            Self::DirName(s) | Self::FolderContents(s) | Self::FileName(s) | Self::More(s) => {
+                let size = u16::try_from(s.len() + 1).map_err(|_| too_long(s.len()))?;
+                data.extend(size.to_be_bytes());
                 data.extend(s.bytes());
                 data.push(0);
             }
             Self::Code(code) => {
-                data.extend(0x4D28_u16.to_be_bytes().into_iter());
-                data.extend(hex!("0004").into_iter());
+                data.extend(hex!("0004"));
                 data.extend(code.to_be_bytes());
             }
             Self::Contents(s) => {
-                data.extend(0x4D20_u16.to_be_bytes().into_iter());
-                data.extend(((s.len()) as u16).to_be_bytes());
-                data.extend(s.into_iter());
-            }
-            Self::More(s) => {
-                data.extend(0x4D24_u16.to_be_bytes().into_iter());
-                data.extend(((s.len() + 1) as u16).to_be_bytes());
-                data.extend(s.bytes());
-                data.push(0);
+                match contents_width {
+                    SizeWidth::Narrow => data.extend(u16::try_from(s.len()).map_err(|_| too_long(s.len()))?.to_be_bytes()),
+                    SizeWidth::Wide => data.extend(u32::try_from(s.len()).map_err(|_| too_long(s.len()))?.to_be_bytes()),
+                }
+                data.extend(s);
             }
-            _ => unimplemented!(),
         };
-        data
+        Ok(data)
+    }
+
+    pub fn kind(&self) -> ParamKind {
+        match self {
+            Self::Cmd(_) => ParamKind::Cmd,
+            Self::Uuid(_) => ParamKind::Uuid,
+            Self::DirName(_) => ParamKind::DirName,
+            Self::FolderContents(_) => ParamKind::FolderContents,
+            Self::FileName(_) => ParamKind::FileName,
+            Self::Contents(_) => ParamKind::Contents,
+            Self::More(_) => ParamKind::More,
+            Self::Code(_) => ParamKind::Code,
+        }
+    }
+
+    /// the value of a string param (DirName, FolderContents, FileName, More)
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::DirName(s) | Self::FolderContents(s) | Self::FileName(s) | Self::More(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// the raw bytes of a Contents param
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Self::Contents(contents) => Some(contents),
+            _ => None,
+        }
     }
 }
 
+/// the tag of a [`Param`] without its value, used to look params up in a parsed message
 #[repr(u16)]
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum ParamKind {
+    Cmd = 0x4D00,
+    Uuid = 0x4D08,
+    DirName = 0x4D14,
+    FolderContents = 0x4D18,
+    FileName = 0x4D1C,
+    Contents = 0x4D20,
+    More = 0x4D24,
+    Code = 0x4D28,
+}
+
+impl ParamKind {
+    pub fn tag(self) -> u16 {
+        self as u16
+    }
+}
+
+#[repr(u16)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
 pub enum Command {
     Init = 0x0002,
     GetSessionFolder = 0x0003, // used in claris, seems to prompt the server to give you a temp folder for your UUID
@@ -100,15 +166,15 @@ pub enum Command {
 }
 
 impl Command {
-    pub fn from_u16(dt: u16) -> Self {
+    pub fn from_u16(dt: u16) -> Option<Self> {
         match dt {
-            2 => Self::Init,
-            3 => Self::GetSessionFolder,
-            4 => Self::ListDir,
-            5 => Self::ReadFile,
-            6 => Self::Upload,
-            7 => Self::Fin,
-            _ => panic!("lmao dont do that"),
+            2 => Some(Self::Init),
+            3 => Some(Self::GetSessionFolder),
+            4 => Some(Self::ListDir),
+            5 => Some(Self::ReadFile),
+            6 => Some(Self::Upload),
+            7 => Some(Self::Fin),
+            _ => None,
         }
     }
 }
@@ -128,7 +194,7 @@ impl Protocol for Command {
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
 pub struct Message {
     data: Vec<u8>,
 }
@@ -149,36 +215,34 @@ impl Message {
     }
 
     pub fn make_init(uuid: [u8; 16]) -> Self {
-        Message::new()
-            .append(Magic::Start)
-            .append(Param::Cmd(Command::Init))
-            .append(Param::Uuid(uuid))
-            .append(Magic::End)
-            .build()
+        MessageBuilder::new()
+            .cmd(Command::Init)
+            .uuid(uuid)
+            .finish()
     }
 
-    pub fn make_list_dir(uuid: [u8; 16], dir: impl AsRef<Path>) -> Self {
-        Message::new()
-            .append(Magic::Start)
-            .append(Param::Cmd(Command::ListDir))
-            .append(Param::Uuid(uuid))
-            .append(Param::DirName(dir.as_ref().to_string_lossy().to_string()))
-            .append(Magic::End)
-            .build()
+    pub fn make_list_dir(uuid: [u8; 16], dir: &RemotePath) -> Self {
+        MessageBuilder::new()
+            .cmd(Command::ListDir)
+            .uuid(uuid)
+            .dir(dir.to_string())
+            .finish()
     }
 
-    pub fn make_read_file(uuid: [u8; 16], dir: impl AsRef<Path>, file: impl AsRef<Path>) -> Self {
-        Message::new()
-            .append(Magic::Start)
-            .append(Param::Cmd(Command::ReadFile))
-            .append(Param::Uuid(uuid))
-            .append(Param::DirName(dir.as_ref().to_string_lossy().to_string()))
-            .append(Param::FileName(file.as_ref().to_string_lossy().to_string()))
-            .append(Magic::End)
-            .build()
+    pub fn make_read_file(uuid: [u8; 16], dir: &RemotePath, file: &str) -> Self {
+        MessageBuilder::new()
+            .cmd(Command::ReadFile)
+            .uuid(uuid)
+            .dir(dir.to_string())
+            .file(file)
+            .finish()
     }
 
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
 
+    /// appends anything, in any order; see [`MessageBuilder`] for building messages the server will accept
     pub fn append(&mut self, msg: impl Protocol) -> &mut Self {
         self.data.append(&mut msg.to_proto_bytes());
         self
@@ -195,13 +259,22 @@ impl Protocol for Message {
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
 pub enum Block {
     Magic(Magic),
     Param(Param),
     Command(Command),
 }
 
+impl Block {
+    pub fn try_to_proto_bytes_with(self, contents_width: SizeWidth) -> Result<Vec<u8>, TooLong> {
+        match self {
+            Self::Param(param) => param.try_to_proto_bytes_with(contents_width),
+            block => Ok(block.to_proto_bytes()),
+        }
+    }
+}
+
 impl Protocol for Block {
     fn to_proto_bytes(self) -> Vec<u8> {
         match self {
@@ -223,7 +296,7 @@ impl Protocol for Vec<Block> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::parse;
+    use crate::{blocks, message, parse};
     use assert_hex::assert_eq_hex;
     #[test]
     fn magic_works() {
@@ -240,16 +313,11 @@ mod tests {
 
     #[test]
     fn message_builder() {
-        let msg = Message::new()
-            .append(Magic::Start)
-            .append(Param::Cmd(Command::Init))
-            .append(Param::Uuid(hex!("c2cd31ed27134010a0dedfc817a341b7")))
-            .append(Magic::End)
-            .build();
-        assert_eq_hex!(
-            &msg.to_proto_bytes(),
-            &hex!("19b0a81d4d00000200024d080010c2cd31ed27134010a0dedfc817a341b7eda9f5ce")
-        );
+        let mut msg = Message::new();
+        for block in blocks![start, cmd: Init, uuid: "c2cd31ed27134010a0dedfc817a341b7", end] {
+            msg.append(block);
+        }
+        assert_eq_hex!(msg.build().as_bytes(), &hex!("19b0a81d4d00000200024d080010c2cd31ed27134010a0dedfc817a341b7eda9f5ce"));
     }
 
     #[test]
@@ -262,37 +330,40 @@ mod tests {
 
     #[test]
     fn test_commutativity() {
-        {
-            let message = hex!("19B0A81DEDA9F5CE");
-            assert_eq!(parse(&message).unwrap().1.to_proto_bytes(), &message);
-        }
-        {
-            let message = hex!("19B0A81D4D080010FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEDA9F5CE");
-            assert_eq!(parse(&message).unwrap().1.to_proto_bytes(), &message);
-        }
-        {
-            let message = hex!("19B0A81D4D1400084141414141414100EDA9F5CE");
-            assert_eq!(parse(&message).unwrap().1.to_proto_bytes(), &message);
-        }
-        {
-            let message = hex!("19B0A81D4D1800084141414141414100EDA9F5CE");
-            assert_eq!(parse(&message).unwrap().1.to_proto_bytes(), &message);
-        }
-        {
-            let message = hex!("19B0A81D4D1C00084141414141414100EDA9F5CE");
-            assert_eq!(parse(&message).unwrap().1.to_proto_bytes(), &message);
-        }
-        {
-            let message = hex!("19B0A81D4D20000741414141414141EDA9F5CE");
-            assert_eq!(parse(&message).unwrap().1.to_proto_bytes(), &message);
-        }
-        {
-            let message = hex!("19B0A81D4D2400084141414141414100EDA9F5CE");
-            assert_eq!(parse(&message).unwrap().1.to_proto_bytes(), &message);
-        }
-        {
-            let message = hex!("19B0A81D4D28000400000009EDA9F5CE");
-            assert_eq!(parse(&message).unwrap().1.to_proto_bytes(), &message);
+        for blocks in [
+            blocks![start, end],
+            blocks![start, uuid: "ffffffffffffffffffffffffffffffff", end],
+            blocks![start, dir: "AAAAAAA", end],
+            blocks![start, folder: "AAAAAAA", end],
+            blocks![start, file: "AAAAAAA", end],
+            blocks![start, contents: b"AAAAAAA", end],
+            blocks![start, more: "AAAAAAA", end],
+            blocks![start, code: 9, end],
+        ] {
+            let message = blocks.to_proto_bytes();
+            assert_eq!(parse(&message).unwrap().1.to_proto_bytes(), message);
         }
     }
+
+    #[test]
+    fn test_too_long() {
+        let contents = vec![0x41; 0x10000];
+        assert_eq!(
+            Param::Contents(contents.clone()).try_to_proto_bytes_with(SizeWidth::Narrow),
+            Err(TooLong { kind: ParamKind::Contents, len: 0x10000 })
+        );
+        assert!(Param::Contents(contents).try_to_proto_bytes_with(SizeWidth::Wide).is_ok());
+        // the nul takes the last byte a string could have had
+        assert!(Param::DirName("A".repeat(0xfffe)).try_to_proto_bytes_with(SizeWidth::Narrow).is_ok());
+        assert!(Param::DirName("A".repeat(0xffff)).try_to_proto_bytes_with(SizeWidth::Wide).is_err());
+    }
+
+    #[test]
+    fn json_works() {
+        let blocks = parse(message! { cmd: ReadFile, contents: b"ABC" }.as_bytes()).unwrap().1.into_blocks();
+        assert_eq!(
+            serde_json::to_string(&blocks).unwrap(),
+            r#"[{"Magic":"Start"},{"Param":{"Cmd":"ReadFile"}},{"Param":{"Contents":"414243"}},{"Magic":"End"}]"#
+        );
+    }
 }
@@ -0,0 +1,96 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+
+use protocol::parse;
+use session::frame::read_handshake;
+use session::{make_handshake, open_handshake, Error, Key, KeyFormat, PublicKey, Result, SecretKey, Session};
+
+pub mod rewrite;
+
+pub use rewrite::{rewrite, Direction, Rule};
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// `host:port` of the real server
+    pub upstream: String,
+    /// what implants seal their handshake to; ours, or the real server's if we have it
+    pub secret_key: SecretKey,
+    /// the real server's key, to seal a fresh handshake to; the implant's handshake is passed on
+    /// as is if there's none
+    pub upstream_key: Option<PublicKey>,
+    pub key_format: KeyFormat,
+    /// used as is instead of deriving one from the fingerprint
+    pub key: Option<Key>,
+    pub rules: Vec<Rule>,
+}
+
+/// accepts connections forever, one thread each
+pub fn serve(listener: TcpListener, config: Config) -> io::Result<()> {
+    session::serve(listener, move |stream| handle_connection(stream, &config))
+}
+
+/// opens the implant's handshake, connects upstream, then passes frames both ways until either
+/// side hangs up
+pub fn handle_connection(mut client: TcpStream, config: &Config) -> Result<()> {
+    let peer = client.peer_addr()?;
+    let handshake = read_handshake(&mut client)?;
+    let (fingerprint, _) = open_handshake(&handshake, &config.secret_key)?;
+    eprintln!("{}: handshake from {} {} ({})", peer, fingerprint.username, fingerprint.version, fingerprint.timestamp);
+    let key = match &config.key {
+        Some(key) => key.clone(),
+        None => fingerprint.session_key(&config.key_format)?,
+    };
+
+    let mut server = TcpStream::connect(&config.upstream)?;
+    match &config.upstream_key {
+        Some(upstream_key) => server.write_all(&make_handshake(&fingerprint, upstream_key))?,
+        None => server.write_all(&handshake)?,
+    }
+
+    let (to_server, to_client) = (server.try_clone()?, client.try_clone()?);
+    let upstream = {
+        let (key, rules) = (key.clone(), config.rules.clone());
+        std::thread::spawn(move || relay(peer, Direction::ToClient, Session::new(server, key.clone()), Session::new(to_client, key), &rules))
+    };
+    let result = relay(peer, Direction::ToServer, Session::new(client, key.clone()), Session::new(to_server, key), &config.rules);
+    let other = upstream.join().unwrap_or_else(|_| Err(io::Error::other("the relay to the client panicked").into()));
+    result.and(other)
+}
+
+// frames from `from` to `to` until one of them is gone, then hangs up on both so the other relay
+// stops too
+fn relay(peer: SocketAddr, direction: Direction, mut from: Session<TcpStream>, mut to: Session<TcpStream>, rules: &[Rule]) -> Result<()> {
+    let result = (|| loop {
+        let plaintext = match from.recv() {
+            Ok(plaintext) => plaintext,
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let rewritten = rewrite(rules, direction, &plaintext);
+        let mut log = format!("{} {}\n", peer, direction);
+        print_blocks(&mut log, &plaintext);
+        if let Some(rewritten) = &rewritten {
+            log.push_str("rewritten to\n");
+            print_blocks(&mut log, rewritten);
+        }
+        println!("{}\n", log);
+        to.send(rewritten.as_deref().unwrap_or(&plaintext))?;
+    })();
+    let _ = from.get_ref().shutdown(Shutdown::Both);
+    let _ = to.get_ref().shutdown(Shutdown::Both);
+    result
+}
+
+fn print_blocks(log: &mut String, plaintext: &[u8]) {
+    match parse(plaintext) {
+        Ok((_, message)) => {
+            for i in message {
+                let _ = writeln!(log, "{:x?}", i);
+            }
+        }
+        Err(e) => {
+            let _ = writeln!(log, "{}: {}", e, hex::encode(plaintext));
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use clap::Parser;
+use proxy::{serve, Config, Rule};
+use session::{parse_key, parse_public_key, parse_secret_key, Key, KeyFormat, PublicKey, SecretKey};
+use std::net::TcpListener;
+
+// sits between an implant and its listening post, printing everything that goes past
+#[derive(Debug, Parser)]
+struct Args {
+    /// `host:port` of the real server
+    upstream: String,
+    #[arg(long, default_value = "0.0.0.0:6666")]
+    listen: String,
+    /// hex of the box secret key implants seal their handshake to
+    #[arg(long, value_parser = parse_secret_key)]
+    secret_key: SecretKey,
+    /// hex of the real server's public key, to seal a fresh handshake to; without it the
+    /// implant's handshake is passed on untouched
+    #[arg(long, value_parser = parse_public_key)]
+    upstream_key: Option<PublicKey>,
+    #[arg(long, default_value_t = KeyFormat::default())]
+    key_format: KeyFormat,
+    /// hex of the session key, instead of deriving it from the fingerprint
+    #[arg(long, value_parser = parse_key)]
+    key: Option<Key>,
+    /// `[>|<]Kind[:old]=new`, e.g. `>DirName=/etc`; repeatable, the first match wins
+    #[arg(long = "rewrite")]
+    rules: Vec<Rule>,
+}
+
+fn main() {
+    let args = Args::parse();
+    let listener = match TcpListener::bind(&args.listen) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("can't listen on {}: {}", args.listen, e);
+            std::process::exit(1);
+        }
+    };
+    eprintln!("proxying {} to {}", args.listen, args.upstream);
+
+    let config = Config {
+        upstream: args.upstream,
+        secret_key: args.secret_key,
+        upstream_key: args.upstream_key,
+        key_format: args.key_format,
+        key: args.key,
+        rules: args.rules,
+    };
+    if let Err(e) = serve(listener, config) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,141 @@
+use std::fmt;
+use std::str::FromStr;
+
+use protocol::*;
+
+/// which way a message is going
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Direction {
+    ToServer,
+    ToClient,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ToServer => f.write_str(">"),
+            Self::ToClient => f.write_str("<"),
+        }
+    }
+}
+
+/// swaps the value of a param in messages going past
+///
+/// written `[>|<]Kind[:old]=new`: `>DirName=/etc` sends every DirName to the server as `/etc`,
+/// `<FolderContents:a.txt=b.txt` renames one entry in listings coming back. without a direction it
+/// goes both ways. Uuid and Contents are hex, Code is a number
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Rule {
+    pub direction: Option<Direction>,
+    /// only params with this value, if set
+    pub from: Option<Param>,
+    pub to: Param,
+}
+
+fn param(kind: &str, value: &str) -> Result<Param, String> {
+    let bytes = |value: &str| hex::decode(value).map_err(|e| format!("{} needs hex: {}", kind, e));
+    Ok(match kind {
+        "Uuid" => Param::Uuid(bytes(value)?.try_into().map_err(|_| "Uuid needs 16 bytes".to_string())?),
+        "DirName" => Param::DirName(value.to_string()),
+        "FolderContents" => Param::FolderContents(value.to_string()),
+        "FileName" => Param::FileName(value.to_string()),
+        "Contents" => Param::Contents(bytes(value)?),
+        "More" => Param::More(value.to_string()),
+        "Code" => Param::Code(value.parse().map_err(|e| format!("Code needs a number: {}", e))?),
+        _ => return Err(format!("can't rewrite {:?} params", kind)),
+    })
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(rule: &str) -> Result<Self, String> {
+        let (direction, rule) = match rule.chars().next() {
+            Some('>') => (Some(Direction::ToServer), &rule[1..]),
+            Some('<') => (Some(Direction::ToClient), &rule[1..]),
+            _ => (None, rule),
+        };
+        let (lhs, to) = rule.split_once('=').ok_or_else(|| format!("{:?} has no `=`", rule))?;
+        let (kind, from) = match lhs.split_once(':') {
+            Some((kind, from)) => (kind, Some(from)),
+            None => (lhs, None),
+        };
+        let to = param(kind, to)?;
+        to.clone().try_to_proto_bytes_with(SizeWidth::Narrow).map_err(|e| e.to_string())?;
+        Ok(Rule { direction, from: from.map(|x| param(kind, x)).transpose()?, to })
+    }
+}
+
+impl Rule {
+    fn applies(&self, direction: Direction, param: &Param) -> bool {
+        self.direction.is_none_or(|x| x == direction)
+            && param.kind() == self.to.kind()
+            && self.from.as_ref().is_none_or(|from| from == param)
+    }
+}
+
+/// `plaintext` with the rules applied, or `None` if none of them matched
+///
+/// messages that don't parse are left alone
+pub fn rewrite(rules: &[Rule], direction: Direction, plaintext: &[u8]) -> Option<Vec<u8>> {
+    let (_, message) = parse(plaintext).ok()?;
+    let mut changed = false;
+    let blocks = message
+        .into_iter()
+        .map(|block| match block {
+            Block::Param(param) => match rules.iter().find(|x| x.applies(direction, &param)) {
+                Some(rule) => {
+                    changed = true;
+                    Block::Param(rule.to.clone())
+                }
+                None => Block::Param(param),
+            },
+            block => block,
+        })
+        .collect::<Vec<_>>();
+    changed.then(|| blocks.to_proto_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+
+    const UUID: [u8; 16] = hex!("000102030405060708090a0b0c0d0f10");
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            ">DirName=/etc".parse(),
+            Ok(Rule { direction: Some(Direction::ToServer), from: None, to: Param::DirName("/etc".to_string()) })
+        );
+        assert_eq!(
+            "<Code:2=0".parse(),
+            Ok(Rule { direction: Some(Direction::ToClient), from: Some(Param::Code(2)), to: Param::Code(0) })
+        );
+        // only the first `=` splits
+        assert_eq!("More=a=b".parse::<Rule>().unwrap().to, Param::More("a=b".to_string()));
+        assert_eq!("Contents=00ff".parse::<Rule>().unwrap().to, Param::Contents(vec![0, 0xff]));
+        assert!("DirName".parse::<Rule>().is_err());
+        assert!("Cmd=1".parse::<Rule>().is_err());
+        assert!("Code=x".parse::<Rule>().is_err());
+        assert!("Uuid=00".parse::<Rule>().is_err());
+        assert!(format!("Contents={}", "00".repeat(0x10000)).parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn test_rewrite() {
+        let rules = ["<FolderContents:secret.txt=boring.txt".parse().unwrap(), ">DirName=/etc".parse().unwrap()];
+        let request = Message::make_list_dir(UUID, &RemotePath::posix("/home"));
+        let rewritten = rewrite(&rules, Direction::ToServer, request.as_bytes()).unwrap();
+        assert_eq!(rewritten, Message::make_list_dir(UUID, &RemotePath::posix("/etc")).as_bytes());
+        // wrong way
+        assert_eq!(rewrite(&rules, Direction::ToClient, request.as_bytes()), None);
+
+        let listing = message! { cmd: ListDir, folder: "a.txt", folder: "secret.txt", code: 0 };
+        let rewritten = rewrite(&rules, Direction::ToClient, listing.as_bytes()).unwrap();
+        assert_eq!(rewritten, message! { cmd: ListDir, folder: "a.txt", folder: "boring.txt", code: 0 }.as_bytes());
+
+        assert_eq!(rewrite(&rules, Direction::ToServer, b"not a message"), None);
+    }
+}
@@ -0,0 +1,53 @@
+use std::net::{SocketAddr, TcpListener};
+
+use client::session::{Fingerprint, KeyFormat, PublicKey};
+use client::{Client, Config};
+use proxy::serve;
+use sodiumoxide::crypto::box_;
+
+// a mock server, and a proxy with its own keypair in front of it
+fn start(name: &str, rules: &[&str]) -> (SocketAddr, PublicKey) {
+    let server = mock_server::testing::spawn(&format!("proxy-{}", name));
+    let (proxy_key, secret_key) = box_::gen_keypair();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let config = proxy::Config {
+        upstream: server.addr.to_string(),
+        secret_key,
+        upstream_key: Some(server.public_key),
+        key_format: KeyFormat::default(),
+        key: None,
+        rules: rules.iter().map(|x| x.parse().unwrap()).collect(),
+    };
+    std::thread::spawn(move || serve(listener, config));
+    (addr, proxy_key)
+}
+
+async fn connect(addr: SocketAddr, server_key: PublicKey) -> Client {
+    let config = Config {
+        server: addr.to_string(),
+        server_key,
+        fingerprint: Fingerprint { username: "bob".to_string(), ..Fingerprint::default() },
+        ..Config::default()
+    };
+    Client::connect(&config).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_passthrough() {
+    let (addr, proxy_key) = start("passthrough", &[]);
+    let mut client = connect(addr, proxy_key).await;
+    assert_eq!(client.ls().await.unwrap(), ["docs/"]);
+    client.cd("docs");
+    assert_eq!(client.get("a.txt").await.unwrap(), b"hello");
+    client.fin().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_rewrite() {
+    let (addr, proxy_key) = start("rewrite", &[">DirName=/docs", "<FolderContents:a.txt=b.txt"]);
+    let mut client = connect(addr, proxy_key).await;
+    // asked for the root, served docs, and the name changed on the way back
+    assert_eq!(client.ls().await.unwrap(), ["b.txt", "data.bin"]);
+    client.fin().await.unwrap();
+}
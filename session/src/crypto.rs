@@ -0,0 +1,249 @@
+use ring::digest::{Context, Digest, SHA256};
+use sodiumoxide::crypto::box_::{PublicKey, SecretKey, PUBLICKEYBYTES};
+use sodiumoxide::crypto::secretbox::Key;
+
+use crate::frame;
+use crate::{Error, Fingerprint, FingerprintError, OsRandom, Randomness, Result};
+
+pub fn htons(u: u16) -> u16 {
+    u.to_be()
+}
+
+pub fn ntohs(u: u16) -> u16 {
+    u16::from_be(u)
+}
+
+pub fn sha256_digest(data: &[u8]) -> Digest {
+    let mut context = Context::new(&SHA256);
+    context.update(data);
+    context.finish()
+}
+
+/// the session key for a rendered [`KeyFormat`](crate::KeyFormat), e.g. `sky+2.1.3.0+1634050056`
+pub fn hash_key(input: &str) -> Key {
+    Key::from_slice(sha256_digest(input.as_bytes()).as_ref()).expect("sha256 is a valid key length")
+}
+
+pub fn length_header(length: u16) -> [u8; 4] {
+    let mut buf = [0u8; 4];
+    buf[0] = 0x12;
+    buf[1] = 0x21; // haha random
+    let size1 = ntohs(0x2112) as u32;
+    let size2 = ((length as u32).wrapping_sub(size1)).wrapping_add(0x10000);
+    let uvar2 = htons((size2 % 0xffff) as u16);
+    buf[2] = (uvar2 & 0xff) as u8;
+    buf[3] = ((uvar2 >> 8) & 0xff) as u8;
+    buf
+}
+
+/// inverse of [`length_header`]
+///
+/// the `% 0xffff` means the encoding has one collision: 0xffff encodes the same as 0, so it decodes as 0.
+/// the size bytes `ffff` are never produced and are rejected
+pub fn decode_length_header(header: [u8; 4]) -> Result<u16> {
+    if header[..2] != [0x12, 0x21] {
+        return Err(Error::LengthHeader(header));
+    }
+    let size = u16::from_be_bytes([header[2], header[3]]);
+    let size1 = ntohs(0x2112);
+    match size {
+        0xffff => Err(Error::LengthHeader(header)),
+        // no wrap around in the encoder
+        size if size >= 0u16.wrapping_sub(size1) => Ok(size.wrapping_add(size1)),
+        // wrapped, and lost one to the % 0xffff on the way
+        size => Ok(size + size1 - 1),
+    }
+}
+
+/// our public key, then the fingerprint boxed for the server in a frame
+pub fn make_handshake(fingerprint: &Fingerprint, server_public_key: &PublicKey) -> Vec<u8> {
+    make_handshake_with(&mut OsRandom, fingerprint, server_public_key)
+}
+
+/// [`make_handshake`] with the keypair and nonce from `random`
+pub fn make_handshake_with(random: &mut impl Randomness, fingerprint: &Fingerprint, server_public_key: &PublicKey) -> Vec<u8> {
+    let (public, private) = random.keypair();
+    let nonce = sodiumoxide::crypto::box_::Nonce(random.nonce());
+    let mut sealed = sodiumoxide::crypto::box_::seal(fingerprint.encode().as_bytes(), &nonce, server_public_key, &private);
+    let mut output = Vec::new();
+    output.extend(public.0);
+    output.extend(length_header((nonce.0.len() + sealed.len()) as u16));
+    output.extend(nonce.0);
+    output.append(&mut sealed);
+    output
+}
+
+/// the other end of [`make_handshake`]: the fingerprint and the client's public key
+pub fn open_handshake(handshake: &[u8], server_secret_key: &SecretKey) -> Result<(Fingerprint, PublicKey)> {
+    use sodiumoxide::crypto::box_::Nonce;
+    let body = PUBLICKEYBYTES + frame::HEADER_LEN + frame::NONCE_LEN;
+    if handshake.len() < body {
+        return Err(Error::ShortFrame(handshake.len()));
+    }
+    let client_public_key = PublicKey::from_slice(&handshake[..PUBLICKEYBYTES]).unwrap();
+    let nonce = Nonce::from_slice(&handshake[body - frame::NONCE_LEN..body]).unwrap();
+    let plaintext = sodiumoxide::crypto::box_::open(&handshake[body..], &nonce, &client_public_key, server_secret_key)
+        .map_err(|_| Error::Handshake)?;
+    let encoded = String::from_utf8(plaintext).map_err(FingerprintError::from)?;
+    Ok((Fingerprint::decode(&encoded)?, client_public_key))
+}
+
+/// hex of a box secret key, for command line args
+pub fn parse_secret_key(key: &str) -> std::result::Result<SecretKey, String> {
+    let key = hex::decode(key.trim()).map_err(|e| e.to_string())?;
+    SecretKey::from_slice(&key).ok_or_else(|| format!("secret key is {} bytes, it needs 32", key.len()))
+}
+
+/// hex of a box public key, for command line args
+pub fn parse_public_key(key: &str) -> std::result::Result<PublicKey, String> {
+    let key = hex::decode(key.trim()).map_err(|e| e.to_string())?;
+    PublicKey::from_slice(&key).ok_or_else(|| format!("public key is {} bytes, it needs 32", key.len()))
+}
+
+/// hex of a session key, for command line args
+pub fn parse_key(key: &str) -> std::result::Result<Key, String> {
+    let key = hex::decode(key.trim()).map_err(|e| e.to_string())?;
+    Key::from_slice(&key).ok_or_else(|| format!("key is {} bytes, it needs 32", key.len()))
+}
+
+/// the whole frame for `message`; anything over [`frame::MAX_PLAINTEXT_LEN`] is refused, the
+/// length header can't say how long it is
+pub fn encrypt(key: &Key, message: Vec<u8>) -> Result<Vec<u8>> {
+    encrypt_with(&mut OsRandom, key, message)
+}
+
+/// [`encrypt`] with the nonce from `random`
+pub fn encrypt_with(random: &mut impl Randomness, key: &Key, message: Vec<u8>) -> Result<Vec<u8>> {
+    if message.len() > frame::MAX_PLAINTEXT_LEN {
+        return Err(Error::TooLong(message.len()));
+    }
+    let nonce = sodiumoxide::crypto::secretbox::Nonce(random.nonce());
+    let cipher = sodiumoxide::crypto::secretbox::seal(message.as_slice(), &nonce, key);
+    let mut output = Vec::new();
+    output.extend(length_header((nonce.0.len() + cipher.len()) as u16));
+    output.extend(nonce.0);
+    output.extend(cipher);
+    Ok(output)
+}
+
+pub fn decrypt(key: &Key, message: &[u8]) -> Result<Vec<u8>> {
+    use sodiumoxide::crypto::secretbox::xsalsa20poly1305::Nonce;
+    let body = frame::HEADER_LEN + frame::NONCE_LEN;
+    if message.len() < body {
+        return Err(Error::ShortFrame(message.len()));
+    }
+    let nonce = Nonce::from_slice(&message[frame::HEADER_LEN..body]).unwrap();
+    sodiumoxide::crypto::secretbox::xsalsa20poly1305::open(&message[body..], &nonce, key).map_err(|_| Error::Decrypt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{KeyFormat, SeededRandom};
+    use hex_literal::hex;
+    use protocol::Message;
+    use protocol::Protocol;
+
+    const SERVER_KEY: [u8; 32] = hex!("e8f1fbc853bdd630b7a2eda38c3100fcbe51227748ea9a6d73d5c18b846fb738");
+
+    #[test]
+    fn test_length_header() {
+        assert_eq!(length_header(0x7f), hex!("1221ee5e"))
+    }
+
+    #[test]
+    fn test_decode_length_header() {
+        assert_eq!(decode_length_header(hex!("1221ee5e")).unwrap(), 0x7f);
+        for length in 0..=0xfffe_u16 {
+            assert_eq!(decode_length_header(length_header(length)).unwrap(), length);
+        }
+        // the one collision
+        assert_eq!(length_header(0xffff), length_header(0));
+        assert_eq!(decode_length_header(length_header(0xffff)).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_decode_length_header_invalid() {
+        for size in 0..=0xffff_u16 {
+            let [a, b] = size.to_be_bytes();
+            match decode_length_header([0x12, 0x21, a, b]) {
+                Ok(length) => assert_eq!(length_header(length), [0x12, 0x21, a, b]),
+                Err(_) => assert_eq!(size, 0xffff),
+            }
+        }
+        assert!(matches!(decode_length_header(hex!("2112ee5e")), Err(Error::LengthHeader(_))));
+        assert!(matches!(decode_length_header(hex!("0000ee5e")), Err(Error::LengthHeader(_))));
+    }
+
+    #[test]
+    fn test_handshake_len() {
+        let handshake = make_handshake(&Fingerprint::default(), &PublicKey(SERVER_KEY));
+        assert_eq!(handshake.len(), 163);
+        assert_eq!(&handshake[32..36], &hex!("1221ee5e"));
+    }
+
+    #[test]
+    fn test_open_handshake() {
+        let (public, secret) = sodiumoxide::crypto::box_::gen_keypair();
+        let fingerprint = Fingerprint { username: "bob".to_string(), extra: vec![("a".to_string(), "b".to_string())], ..Fingerprint::default() };
+        let handshake = make_handshake(&fingerprint, &public);
+        let (opened, client_public_key) = open_handshake(&handshake, &secret).unwrap();
+        assert_eq!(opened, fingerprint);
+        assert_eq!(&client_public_key.0, &handshake[..32]);
+
+        let (_, other) = sodiumoxide::crypto::box_::gen_keypair();
+        assert!(matches!(open_handshake(&handshake, &other), Err(Error::Handshake)));
+        assert!(matches!(open_handshake(&handshake[..40], &secret), Err(Error::ShortFrame(40))));
+    }
+
+    #[test]
+    fn test_hash_key() {
+        let key = Fingerprint::default().session_key(&KeyFormat::default()).unwrap();
+        assert_eq!(hash_key("sky+2.1.3.0+1634050056"), key);
+    }
+
+    #[test]
+    fn test_encrypt_len() {
+        let key = Fingerprint::default().session_key(&KeyFormat::default()).unwrap();
+        assert_eq!(encrypt(&key, Message::make_init(hex!("000102030405060708090a0b0c0d0f10")).to_proto_bytes()).unwrap().len(), 78);
+    }
+
+    #[test]
+    fn test_encrypt_too_long() {
+        let key = Key([7; 32]);
+        let frame = encrypt(&key, vec![0; frame::MAX_PLAINTEXT_LEN]).unwrap();
+        assert_eq!(decode_length_header(frame[..4].try_into().unwrap()).unwrap() as usize, frame::MAX_BODY_LEN);
+        assert!(matches!(encrypt(&key, vec![0; frame::MAX_PLAINTEXT_LEN + 1]), Err(Error::TooLong(_))));
+    }
+
+    #[test]
+    fn test_known_answers() {
+        let mut random = SeededRandom::new([7; 32]);
+        let (server_public_key, server_secret_key) = random.keypair();
+        let handshake = make_handshake_with(&mut random, &Fingerprint::default(), &server_public_key);
+        assert_eq!(
+            handshake,
+            hex!(
+                "f7af59f3e86fca5a2d35c72785c614a2e9ca9663f7472470b81b695c7b68b47c"
+                "1221ee5e60d6dbc9f0650281a75d6a0f3c894d2c7f9d6946dcf791be32a59cbc"
+                "0ae625df6c2add2f7c211511f1d65466cea6615217f973afa51028a3c4d5d63a"
+                "6c3be85c604e7613b9845cafe8d9552c0fc5b3c21259778c3d7ccb536d222e7a"
+                "ca56a0978c8ec724ed38068a3546544ce02704453ec3eff11b1d8c1e643b909b"
+                "f4e2dd"
+            )
+        );
+        assert_eq!(open_handshake(&handshake, &server_secret_key).unwrap().0, Fingerprint::default());
+
+        let key = Fingerprint::default().session_key(&KeyFormat::default()).unwrap();
+        let frame = encrypt_with(&mut random, &key, Message::make_init(hex!("000102030405060708090a0b0c0d0f10")).to_proto_bytes()).unwrap();
+        assert_eq!(
+            frame,
+            hex!(
+                "1221ee2974f01ecfda1fa414cc9866408d48504064468c6fbce764d90db2ec0f"
+                "f427db3301e0cf4cac490ff54ce884bdad5015036159c0d689b24a622b512ac3"
+                "d0ead4baef75742f6733bc24e4e2"
+            )
+        );
+        assert_eq!(decrypt(&key, &frame).unwrap(), Message::make_init(hex!("000102030405060708090a0b0c0d0f10")).to_proto_bytes());
+    }
+}
@@ -0,0 +1,38 @@
+use std::io;
+
+use thiserror::Error;
+
+use crate::FingerprintError;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("connection: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid length header {}", hex::encode(.0))]
+    LengthHeader([u8; 4]),
+    #[error("frame is only {0} bytes")]
+    ShortFrame(usize),
+    #[error("message is {0} bytes, too long for one frame")]
+    TooLong(usize),
+    #[error("frame doesn't open with the session key")]
+    Decrypt,
+    #[error("response doesn't parse")]
+    Parse,
+    #[error("handshake doesn't open with the server key")]
+    Handshake,
+    #[error("timed out")]
+    Timeout,
+    #[error("server answered with code {0:#x}")]
+    Code(u32),
+    #[error("fingerprint: {0}")]
+    Fingerprint(#[from] FingerprintError),
+}
+
+impl Error {
+    /// the stream's out of step with the frames on it, so nothing more read from it can be trusted
+    pub fn is_desync(&self) -> bool {
+        matches!(self, Self::LengthHeader(_) | Self::ShortFrame(_))
+    }
+}
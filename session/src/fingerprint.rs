@@ -0,0 +1,257 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use sodiumoxide::crypto::secretbox::Key;
+use thiserror::Error;
+
+use crate::hash_key;
+
+#[derive(Debug, Error)]
+pub enum FingerprintError {
+    #[error("field isn't valid base64: {0}")]
+    Base64(#[from] base64::DecodeError),
+    #[error("field isn't valid utf8")]
+    Utf8(#[from] std::string::FromUtf8Error),
+    #[error("field {0:?} isn't key=value")]
+    NotKeyValue(String),
+    #[error("missing {0} field")]
+    Missing(&'static str),
+    #[error("invalid timestamp {0:?}")]
+    Timestamp(String),
+    #[error("unbalanced braces in key format {0:?}")]
+    KeyFormat(String),
+    #[error("key format uses {{{0}}}, which isn't in the fingerprint")]
+    UnknownField(String),
+}
+
+/// the implant install we claim to be; sent in the handshake as comma separated base64 `key=value` fields
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Fingerprint {
+    pub username: String,
+    pub version: String,
+    pub os: String,
+    pub timestamp: u64,
+    /// anything past the four known fields, in order
+    pub extra: Vec<(String, String)>,
+}
+
+impl Default for Fingerprint {
+    // the install we captured
+    fn default() -> Self {
+        Fingerprint {
+            username: "sky".to_string(),
+            version: "2.1.3.0-PQF".to_string(),
+            os: "Linux".to_string(),
+            timestamp: 1634050056,
+            extra: Vec::new(),
+        }
+    }
+}
+
+impl Fingerprint {
+    pub fn fields(&self) -> Vec<(String, String)> {
+        let mut fields = vec![
+            ("username".to_string(), self.username.clone()),
+            ("version".to_string(), self.version.clone()),
+            ("os".to_string(), self.os.clone()),
+            ("timestamp".to_string(), self.timestamp.to_string()),
+        ];
+        fields.extend(self.extra.iter().cloned());
+        fields
+    }
+
+    /// the value of a known or extra field
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.fields().into_iter().find(|(k, _)| k == key).map(|(_, value)| value)
+    }
+
+    pub fn session_key(&self, format: &KeyFormat) -> Result<Key, FingerprintError> {
+        format.derive(self)
+    }
+
+    pub fn encode(&self) -> String {
+        self.fields()
+            .into_iter()
+            .map(|(key, value)| STANDARD.encode(format!("{}={}", key, value)))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    pub fn decode(encoded: &str) -> Result<Self, FingerprintError> {
+        let (mut username, mut version, mut os, mut timestamp) = (None, None, None, None);
+        let mut extra = Vec::new();
+        for field in encoded.trim().split(',') {
+            let field = String::from_utf8(STANDARD.decode(field)?)?;
+            let (key, value) = field.split_once('=').ok_or_else(|| FingerprintError::NotKeyValue(field.clone()))?;
+            let value = value.to_string();
+            match key {
+                "username" => username = Some(value),
+                "version" => version = Some(value),
+                "os" => os = Some(value),
+                "timestamp" => timestamp = Some(value.parse().map_err(|_| FingerprintError::Timestamp(value))?),
+                key => extra.push((key.to_string(), value)),
+            }
+        }
+        Ok(Fingerprint {
+            username: username.ok_or(FingerprintError::Missing("username"))?,
+            version: version.ok_or(FingerprintError::Missing("version"))?,
+            os: os.ok_or(FingerprintError::Missing("os"))?,
+            timestamp: timestamp.ok_or(FingerprintError::Missing("timestamp"))?,
+            extra,
+        })
+    }
+}
+
+/// how the session key is built from a fingerprint: `sha256(format)`, with every `{field}` replaced by
+/// that fingerprint field. `{version_number}` is the version without its `-` build suffix
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct KeyFormat(String);
+
+impl Default for KeyFormat {
+    fn default() -> Self {
+        KeyFormat("{username}+{version_number}+{timestamp}".to_string())
+    }
+}
+
+impl FromStr for KeyFormat {
+    type Err = FingerprintError;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        let format = KeyFormat(format.to_string());
+        format.placeholders()?;
+        Ok(format)
+    }
+}
+
+impl fmt::Display for KeyFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl KeyFormat {
+    // literal text and placeholder names, alternating, starting with text
+    fn placeholders(&self) -> Result<Vec<&str>, FingerprintError> {
+        let mut parts = Vec::new();
+        let mut rest = self.0.as_str();
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..].find('}').ok_or_else(|| FingerprintError::KeyFormat(self.0.clone()))? + start;
+            parts.push(&rest[..start]);
+            parts.push(&rest[start + 1..end]);
+            rest = &rest[end + 1..];
+        }
+        if rest.contains('}') {
+            return Err(FingerprintError::KeyFormat(self.0.clone()));
+        }
+        parts.push(rest);
+        Ok(parts)
+    }
+
+    /// the string that gets hashed into the key
+    pub fn render(&self, fingerprint: &Fingerprint) -> Result<String, FingerprintError> {
+        let mut output = String::new();
+        for (i, part) in self.placeholders()?.into_iter().enumerate() {
+            if i % 2 == 0 {
+                output.push_str(part);
+                continue;
+            }
+            let value = match part {
+                "version_number" => fingerprint.version.split('-').next().map(str::to_string),
+                field => fingerprint.get(field),
+            };
+            output.push_str(&value.ok_or_else(|| FingerprintError::UnknownField(part.to_string()))?);
+        }
+        Ok(output)
+    }
+
+    pub fn derive(&self, fingerprint: &Fingerprint) -> Result<Key, FingerprintError> {
+        let rendered = self.render(fingerprint)?;
+        Ok(hash_key(&rendered))
+    }
+}
+
+/// a unix timestamp, or `now`
+pub fn parse_timestamp(timestamp: &str) -> Result<u64, FingerprintError> {
+    if timestamp == "now" {
+        return Ok(SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_secs()).unwrap_or(0));
+    }
+    timestamp.parse().map_err(|_| FingerprintError::Timestamp(timestamp.to_string()))
+}
+
+/// a `key=value` extra field
+pub fn parse_field(field: &str) -> Result<(String, String), FingerprintError> {
+    field
+        .split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| FingerprintError::NotKeyValue(field.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sha256_digest;
+
+    const CAPTURED: &str = "dXNlcm5hbWU9c2t5,dmVyc2lvbj0yLjEuMy4wLVBRRg==,b3M9TGludXg=,dGltZXN0YW1wPTE2MzQwNTAwNTY=";
+
+    #[test]
+    fn test_default_matches_capture() {
+        assert_eq!(Fingerprint::default().encode(), CAPTURED);
+        assert_eq!(Fingerprint::decode(CAPTURED).unwrap(), Fingerprint::default());
+    }
+
+    #[test]
+    fn test_roundtrip_extra() {
+        let fingerprint = Fingerprint {
+            username: "user=name".to_string(),
+            os: "Windows".to_string(),
+            extra: vec![("hostname".to_string(), "box".to_string())],
+            ..Fingerprint::default()
+        };
+        let encoded = fingerprint.encode();
+        assert_eq!(encoded.split(',').count(), 5);
+        assert_eq!(Fingerprint::decode(&encoded).unwrap(), fingerprint);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert!(matches!(Fingerprint::decode("!!!"), Err(FingerprintError::Base64(_))));
+        assert!(matches!(Fingerprint::decode("dXNlcm5hbWU9c2t5"), Err(FingerprintError::Missing("version"))));
+        assert!(matches!(Fingerprint::decode("c2t5"), Err(FingerprintError::NotKeyValue(_))));
+    }
+
+    #[test]
+    fn test_default_key() {
+        let key = Fingerprint::default().session_key(&KeyFormat::default()).unwrap();
+        assert_eq!(key.as_ref(), sha256_digest(b"sky+2.1.3.0+1634050056").as_ref());
+
+        let other = Fingerprint { username: "moon".to_string(), ..Fingerprint::default() };
+        assert_ne!(other.session_key(&KeyFormat::default()).unwrap(), key);
+    }
+
+    #[test]
+    fn test_key_format() {
+        let fingerprint = Fingerprint {
+            extra: vec![("hostname".to_string(), "box".to_string())],
+            ..Fingerprint::default()
+        };
+        let format: KeyFormat = "{os}:{version}:{hostname}".parse().unwrap();
+        assert_eq!(format.render(&fingerprint).unwrap(), "Linux:2.1.3.0-PQF:box");
+        assert_eq!(KeyFormat::default().render(&fingerprint).unwrap(), "sky+2.1.3.0+1634050056");
+        assert!(matches!(
+            "{nope}".parse::<KeyFormat>().unwrap().render(&fingerprint),
+            Err(FingerprintError::UnknownField(_))
+        ));
+        assert!("{username".parse::<KeyFormat>().is_err());
+        assert!("username}".parse::<KeyFormat>().is_err());
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("1634050056").unwrap(), 1634050056);
+        assert!(parse_timestamp("now").unwrap() > 1634050056);
+        assert!(parse_timestamp("yesterday").is_err());
+    }
+}
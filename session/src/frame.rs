@@ -0,0 +1,174 @@
+use std::io::{self, Read};
+
+use sodiumoxide::crypto::box_::PUBLICKEYBYTES;
+use sodiumoxide::crypto::secretbox::MACBYTES;
+
+use crate::{decode_length_header, Result};
+
+pub const HEADER_LEN: usize = 4;
+pub const NONCE_LEN: usize = 24;
+/// what secretbox adds to the plaintext
+pub const MAC_LEN: usize = MACBYTES;
+/// the most the length header can say follows it; 0xffff encodes the same as 0
+pub const MAX_BODY_LEN: usize = 0xfffe;
+/// the longest plaintext that fits in a frame
+pub const MAX_PLAINTEXT_LEN: usize = MAX_BODY_LEN - NONCE_LEN - MAC_LEN;
+
+/// reads a whole handshake (client public key, then a frame) and nothing past it
+pub fn read_handshake<R: Read>(reader: &mut R) -> Result<Vec<u8>> {
+    let mut handshake = vec![0u8; PUBLICKEYBYTES + HEADER_LEN];
+    reader.read_exact(&mut handshake)?;
+    let mut header = [0u8; HEADER_LEN];
+    header.copy_from_slice(&handshake[PUBLICKEYBYTES..]);
+    handshake.resize(handshake.len() + decode_length_header(header)? as usize, 0);
+    reader.read_exact(&mut handshake[PUBLICKEYBYTES + HEADER_LEN..])?;
+    Ok(handshake)
+}
+
+/// splits a byte stream into frames (length header, nonce, ciphertext) however it happens to arrive
+#[derive(Debug, Clone, Default)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// the next whole frame, header included, once all of it has arrived
+    ///
+    /// the size in the header covers the nonce and ciphertext that follow it
+    pub fn next_frame(&mut self) -> Result<Option<Vec<u8>>> {
+        if self.buffer.len() < HEADER_LEN {
+            return Ok(None);
+        }
+        let mut header = [0u8; HEADER_LEN];
+        header.copy_from_slice(&self.buffer[..HEADER_LEN]);
+        let len = HEADER_LEN + decode_length_header(header)? as usize;
+        if self.buffer.len() < len {
+            return Ok(None);
+        }
+        let rest = self.buffer.split_off(len);
+        Ok(Some(std::mem::replace(&mut self.buffer, rest)))
+    }
+
+    /// bytes received that aren't a whole frame yet
+    pub fn pending(&self) -> usize {
+        self.buffer.len()
+    }
+}
+
+/// reads whole frames off a stream
+#[derive(Debug)]
+pub struct FrameReader<R> {
+    inner: R,
+    decoder: FrameDecoder,
+}
+
+impl<R: Read> FrameReader<R> {
+    pub fn new(inner: R) -> Self {
+        FrameReader { inner, decoder: FrameDecoder::new() }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// blocks until a whole frame has arrived; anything read past it is kept for the next call
+    pub fn read_frame(&mut self) -> Result<Vec<u8>> {
+        let mut buf = [0u8; 0x1000];
+        loop {
+            if let Some(frame) = self.decoder.next_frame()? {
+                return Ok(frame);
+            }
+            match self.inner.read(&mut buf) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                Ok(read) => self.decoder.push(&buf[..read]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decrypt, encrypt, length_header, make_handshake, Error, Fingerprint, Key, PublicKey};
+    use hex_literal::hex;
+
+    // hands out at most `chunk` bytes per read
+    struct Trickle<'a> {
+        data: &'a [u8],
+        chunk: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = self.chunk.min(buf.len()).min(self.data.len());
+            buf[..len].copy_from_slice(&self.data[..len]);
+            self.data = &self.data[len..];
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn test_decoder() {
+        let mut decoder = FrameDecoder::new();
+        let mut frame = length_header(3).to_vec();
+        frame.extend(hex!("aabbcc"));
+
+        decoder.push(&frame[..2]);
+        assert_eq!(decoder.next_frame().unwrap(), None);
+        decoder.push(&frame[2..5]);
+        assert_eq!(decoder.next_frame().unwrap(), None);
+        // the rest of the frame and all of the next in one go
+        decoder.push(&frame[5..]);
+        decoder.push(&frame);
+        assert_eq!(decoder.next_frame().unwrap(), Some(frame.clone()));
+        assert_eq!(decoder.next_frame().unwrap(), Some(frame));
+        assert_eq!(decoder.next_frame().unwrap(), None);
+        assert_eq!(decoder.pending(), 0);
+    }
+
+    #[test]
+    fn test_decoder_bad_header() {
+        let mut decoder = FrameDecoder::new();
+        decoder.push(&hex!("deadbeef"));
+        assert!(matches!(decoder.next_frame(), Err(Error::LengthHeader(_))));
+    }
+
+    #[test]
+    fn test_reader() {
+        let key = Key([7; 32]);
+        let first = encrypt(&key, b"first".to_vec()).unwrap();
+        let second = encrypt(&key, b"second".to_vec()).unwrap();
+        let stream = [first.clone(), second.clone()].concat();
+
+        for chunk in [1, 5, 0x1000] {
+            let mut reader = FrameReader::new(Trickle { data: &stream, chunk });
+            assert_eq!(reader.read_frame().unwrap(), first);
+            assert_eq!(decrypt(&key, &reader.read_frame().unwrap()).unwrap(), b"second");
+            assert!(matches!(reader.read_frame(), Err(Error::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof));
+        }
+    }
+
+    #[test]
+    fn test_read_handshake() {
+        let handshake = make_handshake(&Fingerprint::default(), &PublicKey([9; 32]));
+        let stream = [handshake.clone(), encrypt(&Key([7; 32]), b"init".to_vec()).unwrap()].concat();
+        let mut trickle = Trickle { data: &stream, chunk: 3 };
+        assert_eq!(read_handshake(&mut trickle).unwrap(), handshake);
+        // the frame after it is left alone
+        assert_eq!(trickle.data, &stream[handshake.len()..]);
+    }
+}
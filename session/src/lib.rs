@@ -0,0 +1,17 @@
+pub mod crypto;
+pub mod error;
+pub mod fingerprint;
+pub mod frame;
+pub mod random;
+pub mod server;
+mod session;
+
+pub use crypto::*;
+pub use error::{Error, Result};
+pub use fingerprint::{Fingerprint, FingerprintError, KeyFormat};
+pub use frame::{FrameDecoder, FrameReader};
+pub use random::{OsRandom, Randomness, SeededRandom};
+pub use server::serve;
+pub use session::Session;
+pub use sodiumoxide::crypto::box_::{PublicKey, SecretKey};
+pub use sodiumoxide::crypto::secretbox::Key;
@@ -0,0 +1,73 @@
+use sodiumoxide::crypto::box_::{self, PublicKey, SecretKey, Seed};
+use sodiumoxide::crypto::secretbox;
+
+use crate::frame::NONCE_LEN;
+use crate::sha256_digest;
+
+/// where handshake keypairs and frame nonces come from
+///
+/// everything uses [`OsRandom`]; swap in a [`SeededRandom`] to get the same bytes every run
+pub trait Randomness {
+    fn keypair(&mut self) -> (PublicKey, SecretKey);
+    fn nonce(&mut self) -> [u8; NONCE_LEN];
+}
+
+/// libsodium's rng
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsRandom;
+
+impl Randomness for OsRandom {
+    fn keypair(&mut self) -> (PublicKey, SecretKey) {
+        box_::gen_keypair()
+    }
+
+    fn nonce(&mut self) -> [u8; NONCE_LEN] {
+        secretbox::gen_nonce().0
+    }
+}
+
+/// keypairs and nonces hashed from a seed and a counter; predictable, so only for tests and
+/// known-answer vectors
+#[derive(Debug, Clone)]
+pub struct SeededRandom {
+    seed: [u8; 32],
+    counter: u64,
+}
+
+impl SeededRandom {
+    pub fn new(seed: [u8; 32]) -> Self {
+        SeededRandom { seed, counter: 0 }
+    }
+
+    // a different 32 bytes on every call
+    fn next(&mut self, label: &[u8]) -> [u8; 32] {
+        let input = [&self.seed[..], label, &self.counter.to_be_bytes()].concat();
+        self.counter += 1;
+        sha256_digest(&input).as_ref().try_into().expect("sha256 is 32 bytes")
+    }
+}
+
+impl Randomness for SeededRandom {
+    fn keypair(&mut self) -> (PublicKey, SecretKey) {
+        box_::keypair_from_seed(&Seed(self.next(b"keypair")))
+    }
+
+    fn nonce(&mut self) -> [u8; NONCE_LEN] {
+        self.next(b"nonce")[..NONCE_LEN].try_into().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded() {
+        let (mut a, mut b) = (SeededRandom::new([1; 32]), SeededRandom::new([1; 32]));
+        assert_eq!(a.keypair(), b.keypair());
+        assert_eq!(a.nonce(), b.nonce());
+        // moves on after each call
+        assert_ne!(a.nonce(), SeededRandom::new([1; 32]).nonce());
+        assert_ne!(SeededRandom::new([2; 32]).nonce(), SeededRandom::new([1; 32]).nonce());
+    }
+}
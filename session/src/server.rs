@@ -0,0 +1,23 @@
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+
+use crate::Result;
+
+/// accepts connections forever, handing each to `handle` on a thread of its own and logging how
+/// it ended
+pub fn serve(listener: TcpListener, handle: impl Fn(TcpStream) -> Result<()> + Send + Sync + 'static) -> io::Result<()> {
+    let handle = Arc::new(handle);
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let handle = handle.clone();
+        std::thread::spawn(move || {
+            let peer = stream.peer_addr().map(|x| x.to_string()).unwrap_or_default();
+            match handle(stream) {
+                Ok(()) => eprintln!("{}: closed", peer),
+                Err(e) => eprintln!("{}: {}", peer, e),
+            }
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use std::io::{Read, Write};
+
+use protocol::{parse, ParsedMessage};
+use sodiumoxide::crypto::box_::PublicKey;
+use sodiumoxide::crypto::secretbox::Key;
+
+use crate::{decrypt, encrypt, make_handshake, Error, Fingerprint, FrameReader, Result};
+
+/// an established connection: the stream and the key every frame on it is sealed with
+#[derive(Debug)]
+pub struct Session<S> {
+    reader: FrameReader<S>,
+    key: Key,
+}
+
+impl<S: Read + Write> Session<S> {
+    /// for a stream that's already past the handshake
+    pub fn new(stream: S, key: Key) -> Self {
+        Session { reader: FrameReader::new(stream), key }
+    }
+
+    /// sends the handshake for `fingerprint`; `key` is usually [`Fingerprint::session_key`]
+    pub fn handshake(mut stream: S, fingerprint: &Fingerprint, server_public_key: &PublicKey, key: Key) -> Result<Self> {
+        stream.write_all(&make_handshake(fingerprint, server_public_key))?;
+        Ok(Self::new(stream, key))
+    }
+
+    pub fn key(&self) -> &Key {
+        &self.key
+    }
+
+    pub fn get_ref(&self) -> &S {
+        self.reader.get_ref()
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        self.reader.get_mut()
+    }
+
+    /// plaintext to a whole frame, header included
+    pub fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        encrypt(&self.key, plaintext.to_vec())
+    }
+
+    pub fn open(&self, frame: &[u8]) -> Result<Vec<u8>> {
+        decrypt(&self.key, frame)
+    }
+
+    pub fn send(&mut self, plaintext: &[u8]) -> Result<()> {
+        let frame = self.seal(plaintext)?;
+        self.get_mut().write_all(&frame)?;
+        Ok(())
+    }
+
+    /// the plaintext of the next frame
+    pub fn recv(&mut self) -> Result<Vec<u8>> {
+        let frame = self.reader.read_frame()?;
+        self.open(&frame)
+    }
+
+    /// sends one message and waits for the response to it
+    pub fn request(&mut self, plaintext: &[u8]) -> Result<ParsedMessage> {
+        self.send(plaintext)?;
+        let response = self.recv()?;
+        match parse(&response) {
+            Ok((_, message)) => Ok(message),
+            Err(_) => Err(Error::Parse),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyFormat;
+    use hex_literal::hex;
+    use protocol::Message;
+    use std::io;
+
+    // reads from one buffer and writes to another, like a socket
+    struct Duplex {
+        input: io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Duplex {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Duplex {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_request() {
+        let key = Fingerprint::default().session_key(&KeyFormat::default()).unwrap();
+        let request = Message::make_init(hex!("000102030405060708090a0b0c0d0f10"));
+        let response = protocol::message! { cmd: Init, uuid: "000102030405060708090a0b0c0d0f10", code: 0 };
+        let input = encrypt(&key, response.as_bytes().to_vec()).unwrap();
+
+        let mut session = Session::new(Duplex { input: io::Cursor::new(input), output: Vec::new() }, key);
+        let parsed = session.request(request.as_bytes()).unwrap();
+        assert_eq!(parsed.code(), Some(0));
+        let sent = session.get_ref().output.clone();
+        assert_eq!(session.open(&sent).unwrap(), request.as_bytes());
+        assert!(matches!(session.recv(), Err(Error::Io(_))));
+    }
+}